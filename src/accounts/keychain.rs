@@ -0,0 +1,58 @@
+//! Credentials kept in the OS keyring (Secret Service on Linux), one entry
+//! per account under the `omniproxy` service
+
+#[cfg(feature = "keyring")]
+const SERVICE: &str = "omniproxy";
+
+#[cfg(feature = "keyring")]
+pub(super) fn store(id: &str, secret: &str) -> anyhow::Result<()> {
+    keyring::Entry::new(SERVICE, id)?
+        .set_password(secret)
+        .map_err(|e| anyhow::anyhow!("Failed to store {} in the keyring: {}", id, e))
+}
+
+#[cfg(feature = "keyring")]
+pub(super) fn load(id: &str) -> anyhow::Result<String> {
+    keyring::Entry::new(SERVICE, id)?
+        .get_password()
+        .map_err(|e| anyhow::anyhow!("Failed to read {} from the keyring: {}", id, e))
+}
+
+#[cfg(feature = "keyring")]
+pub(super) fn delete(id: &str) -> anyhow::Result<()> {
+    match keyring::Entry::new(SERVICE, id)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(anyhow::anyhow!("Failed to remove {} from the keyring: {}", id, e)),
+    }
+}
+
+/// Fail early when keyring storage is configured but this build lacks it
+#[cfg(feature = "keyring")]
+pub(super) fn check_supported() -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(feature = "keyring"))]
+pub(super) fn check_supported() -> anyhow::Result<()> {
+    unsupported()
+}
+
+#[cfg(not(feature = "keyring"))]
+pub(super) fn store(_id: &str, _secret: &str) -> anyhow::Result<()> {
+    unsupported()
+}
+
+#[cfg(not(feature = "keyring"))]
+pub(super) fn load(_id: &str) -> anyhow::Result<String> {
+    unsupported()
+}
+
+#[cfg(not(feature = "keyring"))]
+pub(super) fn delete(_id: &str) -> anyhow::Result<()> {
+    unsupported()
+}
+
+#[cfg(not(feature = "keyring"))]
+fn unsupported<T>() -> anyhow::Result<T> {
+    anyhow::bail!("This build has no keyring support; rebuild with `--features keyring`")
+}
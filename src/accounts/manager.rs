@@ -1,19 +1,48 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-use super::{Credentials, Provider};
-use crate::config::Config;
+use super::store::{self, AccountsData, Snapshot};
+use super::{keychain, Credentials, Provider};
+use crate::config::{Config, CredentialStorage, CredentialsConfig};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
     pub name: String,
     pub provider: Provider,
     pub credentials: Credentials,
+    #[serde(default, skip_serializing_if = "Egress::is_default")]
+    pub egress: Egress,
+    /// Kept out of rotation until enabled again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+}
+
+/// How an account's upstream traffic leaves this machine, overriding `[http]`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Egress {
+    /// Outbound proxy (`http://`, `https://` or `socks5://`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Local address to connect from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<IpAddr>,
+    /// User-Agent sent instead of the client's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl Egress {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
 }
 
 impl Account {
@@ -26,28 +55,44 @@ impl Account {
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct AccountsData {
-    accounts: Vec<Account>,
+/// Requests served by an account since the server started
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AccountUsage {
+    pub requests: u64,
+    pub errors: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub last_used: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
 }
 
 pub struct AccountManager {
     data: Arc<RwLock<AccountsData>>,
     path: PathBuf,
+    // The file as last read or written, to detect writes by other processes
+    snapshot: Mutex<Snapshot>,
+    storage: CredentialsConfig,
     // Round-robin counters per provider
     counters: HashMap<Provider, AtomicUsize>,
+    // Accounts that were rate limited, until when
+    cooldowns: Mutex<HashMap<(Provider, String), Instant>>,
+    // Accounts taking no new requests while in-flight ones finish
+    draining: Mutex<HashSet<(Provider, String)>>,
+    usage: Mutex<HashMap<(Provider, String), AccountUsage>>,
 }
 
 impl AccountManager {
-    pub async fn load() -> anyhow::Result<Self> {
-        let path = Config::accounts_path()?;
+    pub async fn load(storage: &CredentialsConfig) -> anyhow::Result<Self> {
+        Self::load_from(Config::accounts_path()?, storage).await
+    }
+
+    /// Load the accounts file at `path` rather than the one in the home directory
+    pub(crate) async fn load_from(path: PathBuf, storage: &CredentialsConfig) -> anyhow::Result<Self> {
+        if storage.storage == CredentialStorage::Keyring {
+            keychain::check_supported()?;
+        }
 
-        let data = if path.exists() {
-            let content = tokio::fs::read_to_string(&path).await?;
-            serde_json::from_str(&content)?
-        } else {
-            AccountsData::default()
-        };
+        let (data, snapshot) = read_accounts(&path, storage, Snapshot::default()).await?;
 
         let mut counters = HashMap::new();
         counters.insert(Provider::Codex, AtomicUsize::new(0));
@@ -57,24 +102,68 @@ impl AccountManager {
         Ok(Self {
             data: Arc::new(RwLock::new(data)),
             path,
+            snapshot: Mutex::new(snapshot),
+            storage: storage.clone(),
             counters,
+            cooldowns: Mutex::new(HashMap::new()),
+            draining: Mutex::new(HashSet::new()),
+            usage: Mutex::new(HashMap::new()),
         })
     }
 
-    pub async fn save(&self) -> anyhow::Result<()> {
-        let data = self.data.read().await;
-        let content = serde_json::to_string_pretty(&*data)?;
+    /// Re-read accounts.json, picking up accounts added, removed or edited
+    /// since it was loaded. Credentials refreshed in memory since the file was
+    /// written are kept; returns true when that happened, so the caller can
+    /// save them back.
+    pub async fn reload(&self) -> anyhow::Result<bool> {
+        let previous = self.snapshot.lock().unwrap().clone();
+        let (mut loaded, snapshot) = read_accounts(&self.path, &self.storage, previous).await?;
+        let mut data = self.data.write().await;
+        let mut kept_newer = false;
+
+        for account in &mut loaded.accounts {
+            let current = data.accounts.iter()
+                .find(|a| a.provider == account.provider && a.name == account.name);
 
-        // Ensure parent directory exists
-        if let Some(parent) = self.path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
+            match current {
+                Some(current) if current.credentials.expires_at > account.credentials.expires_at => {
+                    account.credentials = current.credentials.clone();
+                    kept_newer = true;
+                }
+                Some(_) => {}
+                None => tracing::info!("Account added: {}:{}", account.provider, account.name),
+            }
         }
 
-        tokio::fs::write(&self.path, content).await?;
+        for account in &data.accounts {
+            if !loaded.accounts.iter().any(|a| a.provider == account.provider && a.name == account.name) {
+                tracing::info!("Account removed: {}:{}", account.provider, account.name);
+            }
+        }
+
+        *data = loaded;
+        *self.snapshot.lock().unwrap() = snapshot;
+        Ok(kept_newer)
+    }
+
+    /// Write accounts.json, merging changes another process made since it was
+    /// read. Merged-in accounts and credentials are adopted in memory too.
+    pub async fn save(&self) -> anyhow::Result<()> {
+        let mut data = self.data.write().await;
+        let snapshot = self.snapshot.lock().unwrap().clone();
+
+        let path = self.path.clone();
+        let ours = data.clone();
+        let storage = self.storage.clone();
+        let (written, snapshot) =
+            tokio::task::spawn_blocking(move || store::write(&path, ours, &snapshot, &storage)).await??;
+
+        *data = written;
+        *self.snapshot.lock().unwrap() = snapshot;
         Ok(())
     }
 
-    pub async fn add(&mut self, provider: Provider, name: &str, credentials: Credentials) -> anyhow::Result<()> {
+    pub async fn add(&mut self, provider: Provider, name: &str, credentials: Credentials, egress: Egress) -> anyhow::Result<()> {
         let mut data = self.data.write().await;
 
         // Check for duplicate name
@@ -86,6 +175,8 @@ impl AccountManager {
             name: name.to_string(),
             provider,
             credentials,
+            egress,
+            disabled: false,
         });
 
         Ok(())
@@ -120,11 +211,19 @@ impl AccountManager {
             .collect()
     }
 
+    /// Get an account by provider and name
+    pub async fn get(&self, provider: &Provider, name: &str) -> Option<Account> {
+        let data = self.data.read().await;
+        data.accounts.iter()
+            .find(|a| a.provider == *provider && a.name == name)
+            .cloned()
+    }
+
     /// Get the next account for a provider using round-robin
     pub async fn next_account(&self, provider: &Provider) -> Option<Account> {
         let data = self.data.read().await;
         let accounts: Vec<_> = data.accounts.iter()
-            .filter(|a| a.provider == *provider && a.is_valid())
+            .filter(|a| a.provider == *provider && self.is_available(a))
             .collect();
 
         if accounts.is_empty() {
@@ -137,6 +236,106 @@ impl AccountManager {
         accounts.get(idx).map(|a| (*a).clone())
     }
 
+    /// Check if an account can take new requests
+    pub fn is_available(&self, account: &Account) -> bool {
+        account.is_valid() && !account.disabled && !self.is_draining(account) && !self.is_cooling_down(account)
+    }
+
+    /// Take an account out of rotation for the given duration
+    pub fn cool_down(&self, account: &Account, duration: Duration) {
+        tracing::warn!("Cooling down {}:{} for {}s", account.provider, account.name, duration.as_secs());
+        self.cooldowns
+            .lock()
+            .unwrap()
+            .insert((account.provider, account.name.clone()), Instant::now() + duration);
+    }
+
+    /// Check if an account is still cooling down after a rate limit
+    pub fn is_cooling_down(&self, account: &Account) -> bool {
+        let mut cooldowns = self.cooldowns.lock().unwrap();
+        let key = (account.provider, account.name.clone());
+
+        match cooldowns.get(&key) {
+            Some(until) if Instant::now() < *until => true,
+            Some(_) => {
+                cooldowns.remove(&key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Time left on an account's cooldown, if it is cooling down
+    pub fn cooldown_remaining(&self, account: &Account) -> Option<Duration> {
+        let cooldowns = self.cooldowns.lock().unwrap();
+        cooldowns
+            .get(&(account.provider, account.name.clone()))
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+    }
+
+    /// Enable or disable an account. Enabling also ends a drain.
+    pub async fn set_disabled(&self, provider: &Provider, name: &str, disabled: bool) -> anyhow::Result<()> {
+        let mut data = self.data.write().await;
+
+        let account = data.accounts.iter_mut()
+            .find(|a| a.provider == *provider && a.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Account not found: {}:{}", provider, name))?;
+
+        account.disabled = disabled;
+        if !disabled {
+            self.draining.lock().unwrap().remove(&(*provider, name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Stop giving new requests to an account, letting in-flight ones finish.
+    /// Draining lasts until the account is enabled or the server restarts.
+    pub async fn drain(&self, provider: &Provider, name: &str) -> anyhow::Result<()> {
+        if self.get(provider, name).await.is_none() {
+            anyhow::bail!("Account not found: {}:{}", provider, name);
+        }
+        self.draining.lock().unwrap().insert((*provider, name.to_string()));
+        Ok(())
+    }
+
+    pub fn is_draining(&self, account: &Account) -> bool {
+        self.draining
+            .lock()
+            .unwrap()
+            .contains(&(account.provider, account.name.clone()))
+    }
+
+    /// Count an upstream request made with an account
+    pub fn record_request(&self, account: &Account, error: Option<String>) {
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry((account.provider, account.name.clone())).or_default();
+
+        usage.requests += 1;
+        usage.last_used = Some(Utc::now());
+        if error.is_some() {
+            usage.errors += 1;
+            usage.last_error = error;
+        }
+    }
+
+    /// Add the tokens an account's response reported
+    pub fn record_tokens(&self, provider: &Provider, name: &str, prompt_tokens: u64, completion_tokens: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry((*provider, name.to_string())).or_default();
+
+        usage.prompt_tokens += prompt_tokens;
+        usage.completion_tokens += completion_tokens;
+    }
+
+    pub fn usage(&self, account: &Account) -> AccountUsage {
+        self.usage
+            .lock()
+            .unwrap()
+            .get(&(account.provider, account.name.clone()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
     /// Update credentials for an account
     pub async fn update_credentials(&self, provider: &Provider, name: &str, credentials: Credentials) -> anyhow::Result<()> {
         let mut data = self.data.write().await;
@@ -149,3 +348,9 @@ impl AccountManager {
         Ok(())
     }
 }
+
+async fn read_accounts(path: &Path, storage: &CredentialsConfig, previous: Snapshot) -> anyhow::Result<(AccountsData, Snapshot)> {
+    let path = path.to_path_buf();
+    let storage = storage.clone();
+    tokio::task::spawn_blocking(move || store::read(&path, &storage, &previous)).await?
+}
@@ -1,7 +1,10 @@
 mod manager;
 mod provider;
 mod credentials;
+mod keychain;
+mod store;
+mod vault;
 
-pub use manager::{Account, AccountManager};
+pub use manager::{Account, AccountManager, Egress};
 pub use provider::Provider;
 pub use credentials::Credentials;
@@ -33,8 +33,7 @@ impl Provider {
             Provider::Codex => {
                 model_lower.contains("gpt") ||
                 model_lower.contains("codex") ||
-                model_lower.starts_with("o1") ||
-                model_lower.starts_with("o3")
+                Self::is_o_series(&model_lower)
             }
             Provider::Claude => {
                 model_lower.contains("claude") ||
@@ -43,12 +42,16 @@ impl Provider {
                 model_lower.contains("haiku")
             }
             Provider::Gemini => {
-                model_lower.contains("gemini") ||
-                model_lower.contains("flash") ||
-                model_lower.contains("pro") && !model_lower.contains("gpt")
+                model_lower.contains("gemini")
             }
         }
     }
+
+    /// OpenAI reasoning models: o1, o3-pro, o4-mini, ...
+    fn is_o_series(model: &str) -> bool {
+        let mut chars = model.chars();
+        chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit())
+    }
 }
 
 impl std::fmt::Display for Provider {
@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::vault::{Encryption, Vault};
+use super::{keychain, Account, Provider};
+use crate::config::{CredentialStorage, CredentialsConfig};
+
+/// The contents of accounts.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(super) struct AccountsData {
+    /// Bumped on every save, so writers can tell the file changed under them
+    #[serde(default)]
+    pub version: u64,
+    pub accounts: Vec<Account>,
+}
+
+/// accounts.json as this process last read or wrote it
+#[derive(Debug, Clone, Default)]
+pub(super) struct Snapshot {
+    version: u64,
+    modified: Option<SystemTime>,
+    // Each account's settings as read, the base for merging other writers' edits
+    accounts: HashMap<(Provider, String), Value>,
+    // How the file stores credentials
+    storage: CredentialStorage,
+    // Passed back to `read` and `write`, so the key is only derived again
+    // when the file gets a new salt
+    vault: Option<Vault>,
+}
+
+impl Snapshot {
+    fn new(data: &AccountsData, modified: Option<SystemTime>, storage: CredentialStorage, vault: Option<Vault>) -> Self {
+        Self {
+            version: data.version,
+            modified,
+            accounts: data.accounts.iter().map(|a| ((a.provider, a.name.clone()), settings(a))).collect(),
+            storage,
+            vault,
+        }
+    }
+}
+
+/// Read accounts.json, decrypting or fetching credentials from the keyring as
+/// the file says; a missing file has no accounts. `previous` is the last
+/// snapshot, whose key is reused if the file was encrypted with it.
+pub(super) fn read(path: &Path, config: &CredentialsConfig, previous: &Snapshot) -> anyhow::Result<(AccountsData, Snapshot)> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok((AccountsData::default(), Snapshot::default()));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    restrict_permissions(path);
+
+    let mut value: Value = serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))?;
+
+    let vault = match value.get("encryption") {
+        Some(header) => {
+            let header: Encryption = serde_json::from_value(header.clone())?;
+            match &previous.vault {
+                Some(vault) if *vault.header() == header => Some(vault.clone()),
+                _ => Some(Vault::open(config, &header)?),
+            }
+        }
+        None => None,
+    };
+
+    let mut storage = match vault {
+        Some(_) => CredentialStorage::Encrypted,
+        None => CredentialStorage::Plain,
+    };
+    for account in value["accounts"].as_array_mut().into_iter().flatten() {
+        let id = account_id(account);
+        let stored = account["credentials"].take();
+        if stored.get("keyring").is_some() {
+            storage = CredentialStorage::Keyring;
+        }
+        account["credentials"] = open_credentials(&id, stored, vault.as_ref())?;
+    }
+
+    let data: AccountsData = serde_json::from_value(value)
+        .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))?;
+    let snapshot = Snapshot::new(&data, modified(path), storage, vault);
+    Ok((data, snapshot))
+}
+
+/// Write accounts.json while holding the lock shared by every omniproxy
+/// process. If another process wrote the file since `snapshot` was taken,
+/// its changes are merged with ours instead of overwritten. The previous
+/// version is kept as accounts.json.bak, and the new one is renamed into
+/// place so a crash never leaves a half-written file.
+///
+/// Returns what was written, which callers adopt as their new state.
+pub(super) fn write(
+    path: &Path,
+    ours: AccountsData,
+    snapshot: &Snapshot,
+    config: &CredentialsConfig,
+) -> anyhow::Result<(AccountsData, Snapshot)> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+        set_mode(parent, 0o700);
+    }
+
+    // Held until the end of this function, when the file is closed
+    let lock = lock(path)?;
+
+    let (theirs, current) = read(path, config, snapshot)?;
+    let changed = current.version != snapshot.version || current.modified != snapshot.modified;
+
+    let mut data = if changed {
+        tracing::info!("{} changed since it was read, merging", path.display());
+        AccountsData {
+            version: 0,
+            accounts: merge(ours.accounts, theirs.accounts, &snapshot.accounts),
+        }
+    } else {
+        ours
+    };
+    data.version = current.version.max(snapshot.version) + 1;
+
+    let vault = match config.storage {
+        CredentialStorage::Encrypted => match current.vault.or_else(|| snapshot.vault.clone()) {
+            Some(vault) => Some(vault),
+            None => Some(Vault::create(config)?),
+        },
+        _ => None,
+    };
+    let content = match config.storage {
+        CredentialStorage::Plain => serde_json::to_string_pretty(&data)?,
+        _ => serde_json::to_string_pretty(&seal(&data, config, vault.as_ref())?)?,
+    };
+
+    // Keyring entries of accounts that are gone
+    if config.storage == CredentialStorage::Keyring {
+        for (provider, name) in current.accounts.keys() {
+            if !data.accounts.iter().any(|a| a.provider == *provider && a.name == *name) {
+                keychain::delete(&format!("{}:{}", provider, name))?;
+            }
+        }
+    }
+
+    // A backup in the old format would keep credentials the way they are
+    // being moved away from, such as in plaintext
+    let backup = sibling(path, "bak");
+    if current.modified.is_some() && current.storage == config.storage {
+        std::fs::copy(path, &backup)?;
+        set_mode(&backup, 0o600);
+    } else if current.modified.is_some() {
+        tracing::info!(
+            "Moving credentials in {} to {} storage",
+            path.display(),
+            format!("{:?}", config.storage).to_lowercase()
+        );
+        if backup.exists() {
+            std::fs::remove_file(&backup)?;
+        }
+    }
+
+    let tmp = sibling(path, "tmp");
+    let mut file = create_private(&tmp)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp, path)?;
+    sync_dir(path);
+
+    let snapshot = Snapshot::new(&data, modified(path), config.storage, vault);
+    drop(lock);
+    Ok((data, snapshot))
+}
+
+/// The file's JSON, with credentials stored the configured way
+fn seal(data: &AccountsData, config: &CredentialsConfig, vault: Option<&Vault>) -> anyhow::Result<Value> {
+    let mut value = serde_json::to_value(data)?;
+
+    for account in value["accounts"].as_array_mut().into_iter().flatten() {
+        let id = account_id(account);
+        let credentials = serde_json::to_string(&account["credentials"])?;
+
+        account["credentials"] = match (config.storage, vault) {
+            (CredentialStorage::Encrypted, Some(vault)) => json!({ "sealed": vault.seal(&id, credentials.as_bytes())? }),
+            (CredentialStorage::Keyring, _) => {
+                keychain::store(&id, &credentials)?;
+                json!({ "keyring": true })
+            }
+            _ => continue,
+        };
+    }
+
+    if let Some(vault) = vault {
+        value["encryption"] = serde_json::to_value(vault.header())?;
+    }
+    Ok(value)
+}
+
+/// Credentials as stored in the file: plaintext, `{"sealed": ...}` or `{"keyring": true}`
+fn open_credentials(id: &str, stored: Value, vault: Option<&Vault>) -> anyhow::Result<Value> {
+    if let Some(sealed) = stored.get("sealed").and_then(|s| s.as_str()) {
+        let vault = vault.ok_or_else(|| anyhow::anyhow!("Credentials for {} are encrypted but the file has no encryption header", id))?;
+        return Ok(serde_json::from_slice(&vault.unseal(id, sealed)?)?);
+    }
+
+    if stored.get("keyring").is_some() {
+        return Ok(serde_json::from_str(&keychain::load(id)?)?);
+    }
+
+    Ok(stored)
+}
+
+/// `provider:name` of an account in the file's JSON
+fn account_id(account: &Value) -> String {
+    format!(
+        "{}:{}",
+        account["provider"].as_str().unwrap_or_default(),
+        account["name"].as_str().unwrap_or_default()
+    )
+}
+
+/// Combine our accounts with ones another process wrote since we read the
+/// file. An account only one side has was added by that side if we didn't
+/// know it before, and removed by the other side if we did. For accounts
+/// both sides have, the newer credentials win, and each setting we haven't
+/// changed since reading takes their value.
+fn merge(ours: Vec<Account>, theirs: Vec<Account>, known: &HashMap<(Provider, String), Value>) -> Vec<Account> {
+    let key = |a: &Account| (a.provider, a.name.clone());
+    let mut merged = Vec::new();
+
+    for mut account in ours {
+        match theirs.iter().find(|t| key(t) == key(&account)) {
+            Some(their) => {
+                if let Some(base) = known.get(&key(&account)) {
+                    account = merge_settings(account, their, base);
+                }
+                if their.credentials.expires_at > account.credentials.expires_at {
+                    account.credentials = their.credentials.clone();
+                }
+                merged.push(account);
+            }
+            // Removed by them
+            None if known.contains_key(&key(&account)) => {}
+            // Added by us
+            None => merged.push(account),
+        }
+    }
+
+    for account in theirs {
+        // Added by them
+        if !known.contains_key(&key(&account)) && !merged.iter().any(|m| key(m) == key(&account)) {
+            merged.push(account);
+        }
+    }
+
+    merged
+}
+
+/// An account's fields other than its credentials
+fn settings(account: &Account) -> Value {
+    let mut value = serde_json::to_value(account).unwrap_or_default();
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("credentials");
+    }
+    value
+}
+
+/// Three-way merge of our settings with theirs against `base`, as last read
+fn merge_settings(ours: Account, theirs: &Account, base: &Value) -> Account {
+    let (mut merged, their_settings) = (settings(&ours), settings(theirs));
+    let (Some(fields), Some(their_fields)) = (merged.as_object_mut(), their_settings.as_object()) else {
+        return ours;
+    };
+
+    let names: Vec<String> = fields.keys().chain(their_fields.keys()).cloned().collect();
+    for name in names {
+        if fields.get(&name) != base.get(&name) {
+            // Changed by us
+            continue;
+        }
+        match their_fields.get(&name) {
+            Some(value) => fields.insert(name, value.clone()),
+            None => fields.remove(&name),
+        };
+    }
+
+    merged["credentials"] = serde_json::to_value(&ours.credentials).unwrap_or_default();
+    serde_json::from_value(merged).unwrap_or(ours)
+}
+
+/// Take the advisory lock on accounts.json.lock, waiting for other processes
+fn lock(path: &Path) -> anyhow::Result<File> {
+    let lock_path = sibling(path, "lock");
+    let file = open_private(&lock_path, false)?;
+    file.lock()
+        .map_err(|e| anyhow::anyhow!("Failed to lock {}: {}", lock_path.display(), e))?;
+    Ok(file)
+}
+
+/// `accounts.json` -> `accounts.json.<extension>`
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Create or truncate a file only the owner can read
+fn create_private(path: &Path) -> std::io::Result<File> {
+    open_private(path, true)
+}
+
+fn open_private(path: &Path, truncate: bool) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true).write(true).truncate(truncate);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let file = options.open(path)?;
+    // `mode` only applies to new files
+    set_mode(path, 0o600);
+    Ok(file)
+}
+
+/// Tighten a credentials file others can read, which happens when it was
+/// copied or written by an older version
+fn restrict_permissions(path: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Ok(metadata) = std::fs::metadata(path) {
+            if metadata.permissions().mode() & 0o077 != 0 {
+                tracing::warn!("{} was readable by other users; restricting it to its owner", path.display());
+                set_mode(path, 0o600);
+            }
+        }
+        if let Some(parent) = path.parent() {
+            set_mode(parent, 0o700);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// Warn about a secret file other users can read
+pub(super) fn warn_if_exposed(path: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if std::fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o077 != 0) {
+            tracing::warn!("{} is readable by other users; run: chmod 600 {}", path.display(), path.display());
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+fn set_mode(path: &Path, mode: u32) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Err(e) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)) {
+            tracing::warn!("Failed to set permissions on {}: {}", path.display(), e);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+}
+
+/// Persist the rename itself, not just the file contents
+fn sync_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().and_then(|dir| File::open(dir).ok()) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::accounts::{Credentials, Egress};
+
+    /// A fresh directory for one test's accounts.json
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("omniproxy-{}-{:x}", name, rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn account(name: &str, token: &str) -> Account {
+        Account {
+            name: name.to_string(),
+            provider: Provider::Claude,
+            credentials: Credentials::new(token.to_string(), "refresh".to_string(), Utc::now()),
+            egress: Egress::default(),
+            disabled: false,
+        }
+    }
+
+    fn data(accounts: Vec<Account>) -> AccountsData {
+        AccountsData { version: 0, accounts }
+    }
+
+    fn known(accounts: &[Account]) -> HashMap<(Provider, String), Value> {
+        accounts.iter().map(|a| ((a.provider, a.name.clone()), settings(a))).collect()
+    }
+
+    #[test]
+    fn merge_keeps_their_setting_we_left_alone() {
+        let base = account("work", "token");
+        let mut theirs = base.clone();
+        theirs.disabled = true;
+        theirs.egress.proxy = Some("socks5://127.0.0.1:1080".to_string());
+
+        let merged = merge(vec![base.clone()], vec![theirs], &known(&[base]));
+
+        assert!(merged[0].disabled);
+        assert_eq!(merged[0].egress.proxy.as_deref(), Some("socks5://127.0.0.1:1080"));
+    }
+
+    #[test]
+    fn merge_keeps_our_changed_setting() {
+        let base = account("work", "token");
+        let mut ours = base.clone();
+        ours.egress.user_agent = Some("ours".to_string());
+        let mut theirs = base.clone();
+        theirs.egress.user_agent = Some("theirs".to_string());
+        theirs.disabled = true;
+
+        let merged = merge(vec![ours], vec![theirs], &known(&[base]));
+
+        assert_eq!(merged[0].egress.user_agent.as_deref(), Some("ours"));
+        assert!(merged[0].disabled);
+    }
+
+    #[test]
+    fn merge_takes_newer_credentials() {
+        let base = account("work", "old");
+        let mut theirs = base.clone();
+        theirs.credentials = Credentials::new("new".to_string(), "refresh".to_string(), Utc::now() + chrono::Duration::hours(1));
+
+        let merged = merge(vec![base.clone()], vec![theirs], &known(&[base]));
+
+        assert_eq!(merged[0].credentials.access_token, "new");
+    }
+
+    #[test]
+    fn merge_follows_additions_and_removals() {
+        let kept = account("kept", "token");
+        let removed_by_them = account("removed", "token");
+
+        let merged = merge(
+            vec![kept.clone(), removed_by_them.clone(), account("ours", "token")],
+            vec![kept.clone(), account("theirs", "token")],
+            &known(&[kept, removed_by_them]),
+        );
+
+        let names: Vec<&str> = merged.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["kept", "ours", "theirs"]);
+    }
+
+    #[test]
+    fn write_merges_a_concurrent_disable() {
+        let dir = temp_dir("concurrent");
+        let path = dir.join("accounts.json");
+        let config = CredentialsConfig::default();
+        write(&path, data(vec![account("work", "token")]), &Snapshot::default(), &config).unwrap();
+
+        // Two processes read the same file
+        let (mut ours, our_snapshot) = read(&path, &config, &Snapshot::default()).unwrap();
+        let (mut theirs, their_snapshot) = read(&path, &config, &Snapshot::default()).unwrap();
+
+        // They disable the account, then we save a refreshed token
+        theirs.accounts[0].disabled = true;
+        write(&path, theirs, &their_snapshot, &config).unwrap();
+        ours.accounts[0].credentials.expires_at = Utc::now() + chrono::Duration::hours(1);
+        write(&path, ours, &our_snapshot, &config).unwrap();
+
+        let (result, _) = read(&path, &config, &Snapshot::default()).unwrap();
+        assert!(result.accounts[0].disabled);
+        assert!(result.accounts[0].credentials.expires_at > Utc::now());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn encrypted_file_round_trips_without_plaintext() {
+        let dir = temp_dir("encrypted");
+        let key_file = dir.join("master.key");
+        std::fs::write(&key_file, "master").unwrap();
+        let config = CredentialsConfig { storage: CredentialStorage::Encrypted, key_file: Some(key_file) };
+        let path = dir.join("accounts.json");
+
+        write(&path, data(vec![account("work", "secret-token")]), &Snapshot::default(), &config).unwrap();
+        let (read_back, _) = read(&path, &config, &Snapshot::default()).unwrap();
+
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("secret-token"));
+        assert_eq!(read_back.accounts[0].credentials.access_token, "secret-token");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn read_reuses_the_snapshot_key_for_the_same_salt() {
+        let dir = temp_dir("reuse");
+        let key_file = dir.join("master.key");
+        std::fs::write(&key_file, "master").unwrap();
+        let config = CredentialsConfig { storage: CredentialStorage::Encrypted, key_file: Some(key_file.clone()) };
+        let path = dir.join("accounts.json");
+
+        let (_, snapshot) = write(&path, data(vec![account("work", "token")]), &Snapshot::default(), &config).unwrap();
+        // Deriving the key again would need the key file
+        std::fs::remove_file(&key_file).unwrap();
+        let (read_back, snapshot) = read(&path, &config, &snapshot).unwrap();
+        write(&path, read_back, &snapshot, &config).unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
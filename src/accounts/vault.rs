@@ -0,0 +1,182 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::config::CredentialsConfig;
+
+/// Passphrase or key the credentials key is derived from
+const MASTER_KEY_ENV: &str = "OMNIPROXY_MASTER_KEY";
+
+const KDF: &str = "argon2id";
+const CIPHER: &str = "aes-256-gcm";
+const NONCE_LEN: usize = 12;
+
+/// How accounts.json's credentials were encrypted, stored in its `encryption` field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct Encryption {
+    kdf: String,
+    cipher: String,
+    salt: String,
+}
+
+/// Seals credentials with AES-256-GCM, keyed by Argon2id over the master key.
+/// Each account's credentials are bound to its `provider:name`, so they can't
+/// be swapped between accounts in the file.
+#[derive(Clone)]
+pub(super) struct Vault {
+    cipher: Aes256Gcm,
+    header: Encryption,
+}
+
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Vault").field("header", &self.header).finish_non_exhaustive()
+    }
+}
+
+impl Vault {
+    /// A vault with a fresh salt, for a file that isn't encrypted yet
+    pub fn create(config: &CredentialsConfig) -> anyhow::Result<Self> {
+        let header = Encryption {
+            kdf: KDF.to_string(),
+            cipher: CIPHER.to_string(),
+            salt: STANDARD.encode(rand::random::<[u8; 16]>()),
+        };
+        Self::open(config, &header)
+    }
+
+    /// The vault an encrypted file was written with
+    pub fn open(config: &CredentialsConfig, header: &Encryption) -> anyhow::Result<Self> {
+        if header.kdf != KDF || header.cipher != CIPHER {
+            anyhow::bail!("Unsupported credential encryption: {} with {}", header.kdf, header.cipher);
+        }
+
+        Self::derive(&master_key(config)?, header)
+    }
+
+    fn derive(master_key: &str, header: &Encryption) -> anyhow::Result<Self> {
+        let salt = STANDARD.decode(&header.salt)?;
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(master_key.as_bytes(), &salt, &mut key)
+            .map_err(|e| anyhow::anyhow!("Failed to derive credentials key: {}", e))?;
+
+        Ok(Self {
+            cipher: Aes256Gcm::new(&key.into()),
+            header: header.clone(),
+        })
+    }
+
+    pub fn header(&self) -> &Encryption {
+        &self.header
+    }
+
+    /// Encrypt `plaintext` for the account `id`, as base64 of nonce and ciphertext
+    pub fn seal(&self, id: &str, plaintext: &[u8]) -> anyhow::Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: id.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt credentials for {}", id))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(STANDARD.encode(sealed))
+    }
+
+    pub fn unseal(&self, id: &str, sealed: &str) -> anyhow::Result<Vec<u8>> {
+        let sealed = STANDARD.decode(sealed)?;
+        if sealed.len() < NONCE_LEN {
+            anyhow::bail!("Encrypted credentials for {} are truncated", id);
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: id.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt credentials for {}: wrong master key?", id))
+    }
+}
+
+/// The master key from `OMNIPROXY_MASTER_KEY`, or else `credentials.key_file`
+fn master_key(config: &CredentialsConfig) -> anyhow::Result<String> {
+    if let Some(key) = std::env::var(MASTER_KEY_ENV).ok().filter(|k| !k.is_empty()) {
+        return Ok(key);
+    }
+
+    let Some(path) = &config.key_file else {
+        anyhow::bail!("Credentials are encrypted: set {} or [credentials] key_file", MASTER_KEY_ENV);
+    };
+
+    let key = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read key file {}: {}", path.display(), e))?;
+    let key = key.trim();
+    if key.is_empty() {
+        anyhow::bail!("Key file {} is empty", path.display());
+    }
+
+    super::store::warn_if_exposed(path);
+    Ok(key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Encryption {
+        Encryption {
+            kdf: KDF.to_string(),
+            cipher: CIPHER.to_string(),
+            salt: STANDARD.encode([7u8; 16]),
+        }
+    }
+
+    #[test]
+    fn seal_unseal_round_trip() {
+        let vault = Vault::derive("master", &header()).unwrap();
+
+        let sealed = vault.seal("claude:work", b"{\"access_token\":\"x\"}").unwrap();
+
+        assert!(!sealed.contains("access_token"));
+        assert_eq!(vault.unseal("claude:work", &sealed).unwrap(), b"{\"access_token\":\"x\"}");
+    }
+
+    #[test]
+    fn sealing_uses_a_fresh_nonce() {
+        let vault = Vault::derive("master", &header()).unwrap();
+
+        assert_ne!(vault.seal("claude:work", b"same").unwrap(), vault.seal("claude:work", b"same").unwrap());
+    }
+
+    #[test]
+    fn unseal_rejects_other_account() {
+        let vault = Vault::derive("master", &header()).unwrap();
+        let sealed = vault.seal("claude:work", b"secret").unwrap();
+
+        assert!(vault.unseal("claude:personal", &sealed).is_err());
+    }
+
+    #[test]
+    fn unseal_rejects_wrong_key() {
+        let sealed = Vault::derive("master", &header()).unwrap().seal("claude:work", b"secret").unwrap();
+
+        let error = Vault::derive("other", &header()).unwrap().unseal("claude:work", &sealed).unwrap_err();
+
+        assert!(error.to_string().contains("wrong master key"));
+    }
+
+    #[test]
+    fn unseal_rejects_truncated_input() {
+        let vault = Vault::derive("master", &header()).unwrap();
+
+        assert!(vault.unseal("claude:work", &STANDARD.encode([0u8; 4])).is_err());
+    }
+
+    #[test]
+    fn open_rejects_unknown_algorithms() {
+        let header = Encryption { kdf: "scrypt".to_string(), ..header() };
+
+        assert!(Vault::open(&CredentialsConfig::default(), &header).is_err());
+    }
+}
@@ -0,0 +1,36 @@
+use std::io::{BufRead, Write};
+
+/// Have the user open `auth_url` on any machine and paste back where the
+/// provider redirected them. The redirect to 127.0.0.1 fails to load there,
+/// but its URL in the address bar still carries the code.
+pub fn read_code(auth_url: &str, expected_state: &str) -> anyhow::Result<String> {
+    println!("Open this URL in a browser on any machine:\n{}\n", auth_url);
+    println!("After approving, the browser is sent to a 127.0.0.1 page that won't load.");
+    print!("Paste that page's full URL (or just the code): ");
+    std::io::stdout().flush()?;
+
+    let mut input = String::new();
+    std::io::stdin().lock().read_line(&mut input)?;
+
+    parse_code(input.trim(), expected_state)
+}
+
+/// The authorization code from a pasted redirect URL or bare code
+fn parse_code(input: &str, expected_state: &str) -> anyhow::Result<String> {
+    if input.is_empty() {
+        anyhow::bail!("No authorization code entered");
+    }
+
+    let Ok(url) = url::Url::parse(input) else {
+        return Ok(input.to_string());
+    };
+
+    let param = |name: &str| url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned());
+    if let Some(error) = param("error") {
+        anyhow::bail!("OAuth error: {}", error);
+    }
+    if param("state").as_deref() != Some(expected_state) {
+        anyhow::bail!("OAuth error: Invalid state parameter");
+    }
+    param("code").ok_or_else(|| anyhow::anyhow!("No authorization code in the pasted URL"))
+}
@@ -1,28 +1,39 @@
 mod codex;
 mod claude;
 mod gemini;
+mod manual;
+mod oauth;
 mod pkce;
 
 use crate::accounts::{Credentials, Provider};
 
-pub use codex::CodexAuth;
-pub use claude::ClaudeAuth;
-pub use gemini::GeminiAuth;
+use oauth::OAuthClient;
+
+/// How the user completes an OAuth login
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoginMode {
+    /// Open a local browser, which redirects to a callback server on 127.0.0.1
+    #[default]
+    Browser,
+    /// Print the URL and read back the redirect URL or code, for machines without a browser
+    Paste,
+}
 
 /// Perform OAuth login for a provider
-pub async fn oauth_login(provider: &Provider) -> anyhow::Result<Credentials> {
-    match provider {
-        Provider::Codex => CodexAuth::login().await,
-        Provider::Claude => ClaudeAuth::login().await,
-        Provider::Gemini => GeminiAuth::login().await,
-    }
+pub async fn oauth_login(provider: &Provider, client: &reqwest::Client, mode: LoginMode) -> anyhow::Result<Credentials> {
+    oauth_client(provider).login(client, mode).await
 }
 
 /// Refresh access token for a provider
-pub async fn refresh_token(provider: &Provider, refresh_token: &str) -> anyhow::Result<Credentials> {
+pub async fn refresh_token(provider: &Provider, client: &reqwest::Client, refresh_token: &str) -> anyhow::Result<Credentials> {
+    oauth_client(provider).refresh(client, refresh_token).await
+}
+
+/// The OAuth client registration omniproxy uses for a provider
+fn oauth_client(provider: &Provider) -> OAuthClient {
     match provider {
-        Provider::Codex => CodexAuth::refresh(refresh_token).await,
-        Provider::Claude => ClaudeAuth::refresh(refresh_token).await,
-        Provider::Gemini => GeminiAuth::refresh(refresh_token).await,
+        Provider::Codex => codex::oauth(),
+        Provider::Claude => claude::oauth(),
+        Provider::Gemini => gemini::oauth(),
     }
 }
@@ -0,0 +1,417 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::Query,
+    response::Html,
+    routing::get,
+    Router,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+use crate::accounts::Credentials;
+use super::manual::read_code;
+use super::pkce::{generate_pkce, generate_state};
+use super::LoginMode;
+
+/// How long to wait for the browser to come back to the callback server
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// An OAuth client using the authorization code flow with PKCE. Each
+/// provider fills in its own endpoints and client registration.
+#[derive(Debug, Clone)]
+pub struct OAuthClient {
+    pub auth_url: String,
+    pub token_url: String,
+    pub client_id: String,
+    pub scopes: &'static [&'static str],
+    /// Extra query parameters for the authorization URL
+    pub extra_params: &'static [(&'static str, &'static str)],
+    /// Port of the local callback server; a free one is used if it's taken
+    pub redirect_port: u16,
+}
+
+impl OAuthClient {
+    pub async fn login(&self, client: &reqwest::Client, mode: LoginMode) -> anyhow::Result<Credentials> {
+        match mode {
+            LoginMode::Browser => {
+                self.browser_login(client, |auth_url| {
+                    if let Err(e) = open::that(auth_url) {
+                        tracing::warn!("Failed to open browser: {}", e);
+                    }
+                })
+                .await
+            }
+            LoginMode::Paste => self.paste_login(client).await,
+        }
+    }
+
+    /// Send the user's browser to the provider, which redirects it back to a
+    /// callback server on 127.0.0.1 with the authorization code
+    async fn browser_login(&self, client: &reqwest::Client, open_browser: impl FnOnce(&str)) -> anyhow::Result<Credentials> {
+        let (code_verifier, code_challenge) = generate_pkce();
+        let state = generate_state();
+
+        let listener = bind_callback(self.redirect_port).await?;
+        let redirect_uri = redirect_uri(listener.local_addr()?.port());
+        let auth_url = self.authorize_url(&redirect_uri, &state, &code_challenge);
+
+        println!("Opening browser for authentication...");
+        println!("If browser doesn't open, visit:\n{}\n", auth_url);
+        open_browser(&auth_url);
+
+        let code = receive_code(listener, state).await?;
+        self.exchange_code(client, &code, &code_verifier, &redirect_uri).await
+    }
+
+    /// Have the user paste back the redirect URL or code from a browser elsewhere
+    async fn paste_login(&self, client: &reqwest::Client) -> anyhow::Result<Credentials> {
+        let (code_verifier, code_challenge) = generate_pkce();
+        let state = generate_state();
+        let redirect_uri = redirect_uri(self.redirect_port);
+        let auth_url = self.authorize_url(&redirect_uri, &state, &code_challenge);
+
+        let code = read_code(&auth_url, &state)?;
+        self.exchange_code(client, &code, &code_verifier, &redirect_uri).await
+    }
+
+    pub async fn refresh(&self, client: &reqwest::Client, refresh_token: &str) -> anyhow::Result<Credentials> {
+        let token = self
+            .token_request(client, &[("grant_type", "refresh_token"), ("refresh_token", refresh_token)])
+            .await?
+            .map_err(|error| anyhow::anyhow!("Token refresh failed: {}", error))?;
+
+        // Providers that don't rotate refresh tokens leave it out
+        Ok(token.into_credentials(refresh_token))
+    }
+
+    fn authorize_url(&self, redirect_uri: &str, state: &str, code_challenge: &str) -> String {
+        let scope = self.scopes.join(" ");
+        let params = [
+            ("response_type", "code"),
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("scope", &scope),
+            ("state", state),
+            ("code_challenge", code_challenge),
+            ("code_challenge_method", "S256"),
+        ];
+
+        let query: Vec<String> = params
+            .iter()
+            .chain(self.extra_params)
+            .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+            .collect();
+        format!("{}?{}", self.auth_url, query.join("&"))
+    }
+
+    async fn exchange_code(&self, client: &reqwest::Client, code: &str, code_verifier: &str, redirect_uri: &str) -> anyhow::Result<Credentials> {
+        let token = self
+            .token_request(
+                client,
+                &[
+                    ("grant_type", "authorization_code"),
+                    ("code", code),
+                    ("redirect_uri", redirect_uri),
+                    ("code_verifier", code_verifier),
+                ],
+            )
+            .await?
+            .map_err(|error| anyhow::anyhow!("Token exchange failed: {}", error))?;
+
+        Ok(token.into_credentials(""))
+    }
+
+    /// POST a grant to the token endpoint. An error response is returned as
+    /// `Err` with its body, for the caller to report.
+    async fn token_request(&self, client: &reqwest::Client, grant: &[(&str, &str)]) -> anyhow::Result<Result<TokenResponse, String>> {
+        let mut form = grant.to_vec();
+        form.push(("client_id", &self.client_id));
+
+        let response = client
+            .post(&self.token_url)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .form(&form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Ok(Err(response.text().await?));
+        }
+        Ok(Ok(response.json().await?))
+    }
+}
+
+fn redirect_uri(port: u16) -> String {
+    format!("http://127.0.0.1:{}/auth/callback", port)
+}
+
+/// Listen on the callback port, or on any free port when another login or
+/// program is using it
+async fn bind_callback(port: u16) -> anyhow::Result<TcpListener> {
+    match TcpListener::bind(("127.0.0.1", port)).await {
+        Ok(listener) => Ok(listener),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+            tracing::warn!("Port {} is in use; listening for the OAuth callback on {}", port, listener.local_addr()?.port());
+            Ok(listener)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Serve the callback page until the browser brings back the authorization code
+async fn receive_code(listener: TcpListener, expected_state: String) -> anyhow::Result<String> {
+    // Channel to receive the authorization code
+    let (tx, rx) = oneshot::channel::<Result<String, String>>();
+    let tx = Arc::new(std::sync::Mutex::new(Some(tx)));
+
+    let app = Router::new().route(
+        "/auth/callback",
+        get({
+            let tx = Arc::clone(&tx);
+            move |Query(params): Query<CallbackParams>| {
+                let tx = tx.clone();
+                async move {
+                    let result = if params.state != expected_state {
+                        Err("Invalid state parameter".to_string())
+                    } else if let Some(error) = params.error {
+                        Err(error)
+                    } else if let Some(code) = params.code {
+                        Ok(code)
+                    } else {
+                        Err("No authorization code received".to_string())
+                    };
+
+                    if let Some(tx) = tx.lock().unwrap().take() {
+                        let _ = tx.send(result);
+                    }
+
+                    Html(r#"
+                        <html>
+                        <head><title>Authentication Complete</title></head>
+                        <body>
+                            <h1>Authentication successful!</h1>
+                            <p>You can close this window and return to the terminal.</p>
+                            <script>setTimeout(() => window.close(), 2000);</script>
+                        </body>
+                        </html>
+                    "#)
+                }
+            }
+        }),
+    );
+
+    let server = tokio::spawn(async move {
+        axum::serve(listener, app).await
+    });
+
+    let code = tokio::time::timeout(CALLBACK_TIMEOUT, rx).await;
+    server.abort();
+
+    code??.map_err(|e| anyhow::anyhow!("OAuth error: {}", e))
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackParams {
+    code: Option<String>,
+    state: String,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+impl TokenResponse {
+    fn into_credentials(self, current_refresh_token: &str) -> Credentials {
+        let expires_at = Utc::now() + chrono::Duration::seconds(self.expires_in.unwrap_or(3600) as i64);
+
+        Credentials::new(
+            self.access_token,
+            self.refresh_token.unwrap_or_else(|| current_refresh_token.to_string()),
+            expires_at,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use axum::extract::{Form, State};
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Redirect, Response};
+    use axum::routing::post;
+    use axum::Json;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+
+    /// A local authorization server that approves every login and checks
+    /// the PKCE verifier against the challenge it was given
+    #[derive(Default)]
+    struct MockServer {
+        challenge: Mutex<Option<String>>,
+        grants: Mutex<Vec<HashMap<String, String>>>,
+    }
+
+    async fn start(mock: Arc<MockServer>) -> OAuthClient {
+        let app = Router::new()
+            .route("/authorize", get(authorize))
+            .route("/token", post(token))
+            .with_state(mock);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        OAuthClient {
+            auth_url: format!("{}/authorize", base),
+            token_url: format!("{}/token", base),
+            client_id: "test-client".to_string(),
+            scopes: &["read", "write"],
+            extra_params: &[("access_type", "offline")],
+            redirect_port: 0,
+        }
+    }
+
+    async fn authorize(State(mock): State<Arc<MockServer>>, Query(params): Query<HashMap<String, String>>) -> Redirect {
+        assert_eq!(params["client_id"], "test-client");
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(params["access_type"], "offline");
+        *mock.challenge.lock().unwrap() = Some(params["code_challenge"].clone());
+
+        Redirect::to(&format!("{}?code=test-code&state={}", params["redirect_uri"], params["state"]))
+    }
+
+    async fn token(State(mock): State<Arc<MockServer>>, Form(form): Form<HashMap<String, String>>) -> Response {
+        assert_eq!(form["client_id"], "test-client");
+        mock.grants.lock().unwrap().push(form.clone());
+
+        match form["grant_type"].as_str() {
+            "authorization_code" => {
+                let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+                if form["code"] != "test-code" || mock.challenge.lock().unwrap().as_deref() != Some(challenge.as_str()) {
+                    return (StatusCode::BAD_REQUEST, "invalid_grant").into_response();
+                }
+                Json(json!({ "access_token": "access", "refresh_token": "refresh", "expires_in": 600 })).into_response()
+            }
+            "refresh_token" if form["refresh_token"] == "refresh" => {
+                Json(json!({ "access_token": "refreshed", "expires_in": 600 })).into_response()
+            }
+            _ => (StatusCode::BAD_REQUEST, "invalid_grant").into_response(),
+        }
+    }
+
+    /// Stands in for the user's browser by following the provider's redirect
+    /// to the callback server
+    fn browser(auth_url: &str) {
+        let auth_url = auth_url.to_string();
+        tokio::spawn(async move { reqwest::get(auth_url).await.unwrap() });
+    }
+
+    #[tokio::test]
+    async fn browser_login_exchanges_code_with_pkce() {
+        let oauth = start(Arc::default()).await;
+
+        let credentials = oauth.browser_login(&reqwest::Client::new(), browser).await.unwrap();
+
+        assert_eq!(credentials.access_token, "access");
+        assert_eq!(credentials.refresh_token, "refresh");
+        assert!(credentials.expires_at > Utc::now() + chrono::Duration::seconds(500));
+    }
+
+    #[tokio::test]
+    async fn browser_login_sends_the_redirect_uri_it_listened_on() {
+        let mock = Arc::new(MockServer::default());
+        let oauth = start(mock.clone()).await;
+
+        oauth.browser_login(&reqwest::Client::new(), browser).await.unwrap();
+
+        let grants = mock.grants.lock().unwrap();
+        assert!(grants[0]["redirect_uri"].starts_with("http://127.0.0.1:"));
+        assert!(grants[0]["redirect_uri"].ends_with("/auth/callback"));
+    }
+
+    #[tokio::test]
+    async fn callback_rejects_wrong_state() {
+        let listener = bind_callback(0).await.unwrap();
+        let callback = format!("{}?code=test-code&state=forged", redirect_uri(listener.local_addr().unwrap().port()));
+        tokio::spawn(async move { reqwest::get(callback).await.unwrap() });
+
+        let error = receive_code(listener, "expected".to_string()).await.unwrap_err();
+
+        assert!(error.to_string().contains("Invalid state"));
+    }
+
+    #[tokio::test]
+    async fn callback_reports_provider_error() {
+        let listener = bind_callback(0).await.unwrap();
+        let callback = format!("{}?error=access_denied&state=expected", redirect_uri(listener.local_addr().unwrap().port()));
+        tokio::spawn(async move { reqwest::get(callback).await.unwrap() });
+
+        let error = receive_code(listener, "expected".to_string()).await.unwrap_err();
+
+        assert_eq!(error.to_string(), "OAuth error: access_denied");
+    }
+
+    #[tokio::test]
+    async fn busy_redirect_port_falls_back_to_a_free_one() {
+        let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let listener = bind_callback(port).await.unwrap();
+
+        assert_ne!(listener.local_addr().unwrap().port(), port);
+    }
+
+    #[tokio::test]
+    async fn refresh_keeps_refresh_token_when_not_rotated() {
+        let oauth = start(Arc::default()).await;
+
+        let credentials = oauth.refresh(&reqwest::Client::new(), "refresh").await.unwrap();
+
+        assert_eq!(credentials.access_token, "refreshed");
+        assert_eq!(credentials.refresh_token, "refresh");
+    }
+
+    #[tokio::test]
+    async fn refresh_failure_includes_response_body() {
+        let oauth = start(Arc::default()).await;
+
+        let error = oauth.refresh(&reqwest::Client::new(), "revoked").await.unwrap_err();
+
+        assert_eq!(error.to_string(), "Token refresh failed: invalid_grant");
+    }
+
+    #[test]
+    fn authorize_url_encodes_params() {
+        let oauth = OAuthClient {
+            auth_url: "https://example.com/authorize".to_string(),
+            token_url: String::new(),
+            client_id: "client".to_string(),
+            scopes: &["openid", "email"],
+            extra_params: &[("prompt", "consent")],
+            redirect_port: 8484,
+        };
+
+        let url = oauth.authorize_url(&redirect_uri(8484), "state", "challenge");
+
+        assert_eq!(
+            url,
+            "https://example.com/authorize?response_type=code&client_id=client\
+             &redirect_uri=http%3A%2F%2F127.0.0.1%3A8484%2Fauth%2Fcallback&scope=openid%20email\
+             &state=state&code_challenge=challenge&code_challenge_method=S256&prompt=consent"
+        );
+    }
+}
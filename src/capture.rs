@@ -0,0 +1,434 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use axum::body::{Body, Bytes};
+use axum::http::HeaderMap;
+use chrono::Utc;
+use futures::StreamExt;
+use serde_json::{json, Map, Value};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config::{CaptureConfig, Config};
+
+/// Stage files, in the order a request passes through them
+pub const INBOUND_REQUEST: &str = "1-inbound-request.json";
+pub const UPSTREAM_REQUEST: &str = "2-upstream-request.json";
+pub const UPSTREAM_RESPONSE: &str = "3-upstream-response";
+pub const RESPONSE: &str = "4-response";
+
+/// Shown in place of secrets
+const REDACTED: &str = "[REDACTED]";
+
+/// Headers that carry credentials
+const SECRET_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "x-goog-api-key",
+    "api-key",
+    "cookie",
+    "set-cookie",
+];
+
+/// JSON fields that carry credentials
+const SECRET_FIELDS: &[&str] = &["access_token", "refresh_token", "api_key", "apiKey", "id_token", "client_secret"];
+
+/// Debug capture of one request, written to its own directory under
+/// `~/.omniproxy/captures`. Writes are best effort: a failure is logged and
+/// never fails the request.
+///
+/// Files are written by a task of the capture's own, so requests never wait
+/// on the disk. It keeps streamed stage files open until every clone of the
+/// capture is dropped.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    id: String,
+    dir: PathBuf,
+    writer: mpsc::UnboundedSender<WriterOp>,
+}
+
+/// Work for a capture's writer task
+#[derive(Debug)]
+enum WriterOp {
+    /// Replace a stage file
+    Write { stage: String, content: Vec<u8> },
+    /// Add to a streamed stage file
+    Append { stage: String, content: Bytes },
+    /// Flush the open stage files, then reply
+    Flush(oneshot::Sender<()>),
+}
+
+impl Capture {
+    /// Start a capture in a new directory. Old ones are removed by `prune`.
+    pub async fn start(config: &CaptureConfig) -> anyhow::Result<Self> {
+        let root = captures_dir(config)?;
+
+        // Sortable by time, unique enough for concurrent requests
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        let id = format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S%.3f"), &suffix[..8]);
+        let dir = root.join(&id);
+
+        let create = dir.clone();
+        tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&root)?;
+            std::fs::create_dir(&create)
+        })
+        .await??;
+
+        Ok(Self::new(id, dir))
+    }
+
+    /// Open an existing capture by id
+    pub fn open(config: &CaptureConfig, id: &str) -> anyhow::Result<Self> {
+        if id.contains(['/', '\\']) || id.starts_with('.') {
+            anyhow::bail!("Invalid capture id: {}", id);
+        }
+
+        let dir = captures_dir(config)?.join(id);
+        if !dir.is_dir() {
+            anyhow::bail!("Capture not found: {}", id);
+        }
+
+        Ok(Self::new(id.to_string(), dir))
+    }
+
+    fn new(id: String, dir: PathBuf) -> Self {
+        let (writer, ops) = mpsc::unbounded_channel();
+        tokio::spawn(
+            Writer {
+                id: id.clone(),
+                dir: dir.clone(),
+                open: HashMap::new(),
+            }
+            .run(ops),
+        );
+        Self { id, dir, writer }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Read a JSON stage file
+    pub fn read_json(&self, stage: &str) -> anyhow::Result<Value> {
+        let content = std::fs::read(self.dir.join(stage))?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Record a request: method, URL, headers and body, with secrets redacted
+    pub fn write_request(&self, stage: &str, method: &str, url: &str, headers: Option<&HeaderMap>, body: &[u8]) {
+        let mut record = json!({
+            "method": method,
+            "url": redact_url(url),
+            "body": body_json(body),
+        });
+        if let Some(headers) = headers {
+            record["headers"] = redact_headers(headers);
+        }
+        self.write_json(stage, &record);
+    }
+
+    /// Write a JSON stage file, with secrets redacted
+    pub fn write_json(&self, stage: &str, value: &Value) {
+        match serde_json::to_vec_pretty(&redact_json(value)) {
+            Ok(content) => self.write(stage, &content),
+            Err(e) => tracing::warn!("Failed to encode capture {}: {}", stage, e),
+        }
+    }
+
+    /// Write a stage file as is
+    pub fn write(&self, stage: &str, content: &[u8]) {
+        self.send(WriterOp::Write {
+            stage: stage.to_string(),
+            content: content.to_vec(),
+        });
+    }
+
+    /// Append to a stage file, for streamed bodies
+    pub fn append(&self, stage: &str, content: Bytes) {
+        self.send(WriterOp::Append {
+            stage: stage.to_string(),
+            content,
+        });
+    }
+
+    /// Copy a body into a stage file as it is sent
+    pub fn tee(&self, stage: &str, body: Body) -> Body {
+        let capture = self.clone();
+        let stage = stage.to_string();
+
+        let stream = body.into_data_stream().map(move |chunk| {
+            if let Ok(bytes) = &chunk {
+                capture.append(&stage, bytes.clone());
+            }
+            chunk
+        });
+
+        Body::from_stream(stream)
+    }
+
+    /// Wait until everything sent so far is on disk
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        self.send(WriterOp::Flush(done));
+        let _ = flushed.await;
+    }
+
+    fn send(&self, op: WriterOp) {
+        if self.writer.send(op).is_err() {
+            tracing::warn!("Capture {} writer has stopped", self.id);
+        }
+    }
+}
+
+/// Writes one capture's files, off the request path
+struct Writer {
+    id: String,
+    dir: PathBuf,
+    /// Streamed stage files, open until the capture is dropped
+    open: HashMap<String, BufWriter<File>>,
+}
+
+impl Writer {
+    async fn run(mut self, mut ops: mpsc::UnboundedReceiver<WriterOp>) {
+        while let Some(op) = ops.recv().await {
+            match op {
+                WriterOp::Write { stage, content } => {
+                    let result = tokio::fs::write(self.dir.join(&stage), content).await;
+                    report(&self.id, &stage, result);
+                }
+                WriterOp::Append { stage, content } => {
+                    let result = self.append(&stage, &content).await;
+                    report(&self.id, &stage, result);
+                }
+                WriterOp::Flush(done) => {
+                    self.flush().await;
+                    let _ = done.send(());
+                }
+            }
+        }
+        self.flush().await;
+    }
+
+    async fn append(&mut self, stage: &str, content: &[u8]) -> std::io::Result<()> {
+        let file = match self.open.entry(stage.to_string()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let file = OpenOptions::new().create(true).append(true).open(self.dir.join(stage)).await?;
+                entry.insert(BufWriter::new(file))
+            }
+        };
+        file.write_all(content).await
+    }
+
+    async fn flush(&mut self) {
+        for (stage, file) in &mut self.open {
+            report(&self.id, stage, file.flush().await);
+        }
+    }
+}
+
+fn report(id: &str, stage: &str, result: std::io::Result<()>) {
+    if let Err(e) = result {
+        tracing::warn!("Failed to write capture {}/{}: {}", id, stage, e);
+    }
+}
+
+/// Stage file name for a response body, by its content type
+pub fn response_stage(stage: &str, headers: &HeaderMap) -> String {
+    let is_sse = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/event-stream"));
+    format!("{}.{}", stage, if is_sse { "sse" } else { "json" })
+}
+
+/// Directory holding all captures
+pub fn captures_dir(config: &CaptureConfig) -> anyhow::Result<PathBuf> {
+    match &config.dir {
+        Some(dir) => Ok(dir.clone()),
+        None => Ok(Config::dir()?.join("captures")),
+    }
+}
+
+/// Remove the oldest captures beyond `capture.max_captures`. Blocking; run
+/// it off the async runtime.
+pub fn prune(config: &Config) -> anyhow::Result<()> {
+    // No request outlives the HTTP client timeout; with none set, allow an hour
+    let in_flight = match config.http.timeout_secs {
+        0 => 3600,
+        secs => secs,
+    };
+    prune_dir(&captures_dir(&config.capture)?, config.capture.max_captures, Duration::from_secs(in_flight))
+}
+
+/// Remove the oldest captures so at most `keep` remain, sparing any changed
+/// within `in_flight`, which a request may still be writing
+fn prune_dir(root: &Path, keep: usize, in_flight: Duration) -> anyhow::Result<()> {
+    let mut captures: Vec<PathBuf> = match std::fs::read_dir(root) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    if captures.len() <= keep {
+        return Ok(());
+    }
+
+    // Names start with a timestamp, so they sort oldest first
+    captures.sort();
+    let cutoff = SystemTime::now() - in_flight;
+    for path in &captures[..captures.len() - keep] {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified());
+        if modified.is_ok_and(|modified| modified > cutoff) {
+            continue;
+        }
+        if let Err(e) = std::fs::remove_dir_all(path) {
+            tracing::warn!("Failed to remove old capture {}: {}", path.display(), e);
+        }
+    }
+
+    Ok(())
+}
+
+/// A body as JSON, or as a string when it isn't JSON
+fn body_json(body: &[u8]) -> Value {
+    serde_json::from_slice(body).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()))
+}
+
+fn redact_headers(headers: &HeaderMap) -> Value {
+    let mut out = Map::new();
+    for (name, value) in headers {
+        let value = if SECRET_HEADERS.contains(&name.as_str()) {
+            REDACTED.to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        out.insert(name.to_string(), Value::String(value));
+    }
+    Value::Object(out)
+}
+
+/// Redact credential fields anywhere in a JSON value
+fn redact_json(value: &Value) -> Value {
+    match value {
+        Value::Object(obj) => Value::Object(
+            obj.iter()
+                .map(|(key, value)| {
+                    let value = if SECRET_FIELDS.contains(&key.as_str()) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact_json(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_json).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Redact `key=` query parameters
+fn redact_url(url: &str) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+
+    let pairs: Vec<(String, String)> = parsed
+        .query_pairs()
+        .map(|(k, v)| {
+            let v = if k == "key" { REDACTED.to_string() } else { v.into_owned() };
+            (k.into_owned(), v)
+        })
+        .collect();
+
+    if pairs.is_empty() {
+        return url.to_string();
+    }
+
+    parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    parsed.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_removes_oldest_but_spares_recent() {
+        let root = std::env::temp_dir().join(format!("omniproxy-captures-{:x}", rand::random::<u64>()));
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        for (i, name) in ["20250101T000000.000-a", "20250101T000001.000-b", "20250101T000002.000-c", "20250101T000003.000-d"]
+            .iter()
+            .enumerate()
+        {
+            let dir = root.join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            // The first two finished long ago; the others may still be written
+            if i < 2 {
+                std::fs::File::open(&dir).unwrap().set_modified(hour_ago).unwrap();
+            }
+        }
+
+        prune_dir(&root, 1, Duration::from_secs(600)).unwrap();
+
+        let mut left: Vec<String> = std::fs::read_dir(&root)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["20250101T000002.000-c", "20250101T000003.000-d"]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn prune_ignores_missing_dir() {
+        let root = std::env::temp_dir().join(format!("omniproxy-captures-{:x}", rand::random::<u64>()));
+
+        prune_dir(&root, 0, Duration::ZERO).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_stages_and_streamed_bodies() {
+        let root = std::env::temp_dir().join(format!("omniproxy-captures-{:x}", rand::random::<u64>()));
+        let config = CaptureConfig {
+            enabled: true,
+            dir: Some(root.clone()),
+            max_captures: 10,
+        };
+        let capture = Capture::start(&config).await.unwrap();
+
+        capture.write_json(INBOUND_REQUEST, &json!({ "model": "m", "api_key": "sk-1" }));
+        let chunks = ["data: 1\n\n", "data: 2\n\n"].map(|c| Ok::<_, std::io::Error>(Bytes::from_static(c.as_bytes())));
+        let body = capture.tee("4-response.sse", Body::from_stream(futures::stream::iter(chunks)));
+        let sent = http_body_util::BodyExt::collect(body).await.unwrap().to_bytes();
+        capture.flush().await;
+
+        let opened = Capture::open(&config, capture.id()).unwrap();
+        assert_eq!(opened.read_json(INBOUND_REQUEST).unwrap(), json!({ "model": "m", "api_key": REDACTED }));
+        assert_eq!(std::fs::read(root.join(capture.id()).join("4-response.sse")).unwrap(), sent);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn redacts_secrets() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer sk-123".parse().unwrap());
+        headers.insert("content-type", "application/json".parse().unwrap());
+
+        assert_eq!(redact_headers(&headers), json!({ "authorization": REDACTED, "content-type": "application/json" }));
+        assert_eq!(
+            redact_json(&json!({ "a": [{ "refresh_token": "r" }], "model": "m" })),
+            json!({ "a": [{ "refresh_token": REDACTED }], "model": "m" })
+        );
+        assert_eq!(redact_url("https://x.test/v1?key=abc&alt=sse"), "https://x.test/v1?key=%5BREDACTED%5D&alt=sse");
+    }
+}
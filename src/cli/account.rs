@@ -1,7 +1,11 @@
+use std::net::IpAddr;
+
 use clap::{Args, Subcommand};
 
-use crate::accounts::{AccountManager, Provider};
-use crate::auth;
+use crate::accounts::{AccountManager, Egress, Provider};
+use crate::auth::{self, LoginMode};
+use crate::config::Config;
+use crate::http::HttpClients;
 
 #[derive(Args)]
 pub struct AccountCommand {
@@ -18,6 +22,18 @@ pub enum AccountAction {
         /// Account name (optional)
         #[arg(long)]
         name: Option<String>,
+        /// Outbound proxy for this account (http://, https:// or socks5://)
+        #[arg(long)]
+        proxy: Option<String>,
+        /// Local address to connect from
+        #[arg(long)]
+        bind_address: Option<IpAddr>,
+        /// User-Agent for this account's upstream requests
+        #[arg(long)]
+        user_agent: Option<String>,
+        /// Don't open a browser; paste back the redirect URL or code instead (for SSH sessions)
+        #[arg(long)]
+        no_browser: bool,
     },
     /// List all accounts
     List,
@@ -29,20 +45,34 @@ pub enum AccountAction {
 }
 
 pub async fn handle(cmd: AccountCommand) -> anyhow::Result<()> {
-    let manager = AccountManager::load().await?;
+    let config = Config::load().await?;
+    let manager = AccountManager::load(&config.credentials).await?;
 
     match cmd.action {
-        AccountAction::Add { provider, name } => {
+        AccountAction::Add { provider, name, proxy, bind_address, user_agent, no_browser } => {
             let provider = Provider::from_str(&provider)?;
             let name = name.unwrap_or_else(|| format!("{}-{}", provider.as_str(), 1));
 
             println!("Adding {} account: {}", provider.as_str(), name);
-            println!("Opening browser for OAuth login...");
+            let mode = if no_browser {
+                LoginMode::Paste
+            } else {
+                println!("Opening browser for OAuth login...");
+                LoginMode::Browser
+            };
 
-            let credentials = auth::oauth_login(&provider).await?;
+            // Log in through the same egress the account will use
+            let egress = Egress {
+                proxy,
+                bind_address,
+                user_agent,
+            };
+            let clients = HttpClients::new(&config.http)?;
+            let client = clients.for_egress(&provider, &name, &egress)?;
+            let credentials = auth::oauth_login(&provider, &client, mode).await?;
 
             let mut manager = manager;
-            manager.add(provider, &name, credentials).await?;
+            manager.add(provider, &name, credentials, egress).await?;
             manager.save().await?;
 
             println!("Account added: {}:{}", provider.as_str(), name);
@@ -60,7 +90,8 @@ pub async fn handle(cmd: AccountCommand) -> anyhow::Result<()> {
                     println!("{}:", provider.as_str());
                     for acc in accounts {
                         let status = if acc.is_valid() { "✓" } else { "✗" };
-                        println!("  {} {} (expires: {})", status, acc.name, acc.expires_at());
+                        let disabled = if acc.disabled { ", disabled" } else { "" };
+                        println!("  {} {} (expires: {}{})", status, acc.name, acc.expires_at(), disabled);
                     }
                 }
             }
@@ -1,5 +1,6 @@
 pub mod account;
 pub mod models;
+pub mod replay;
 pub mod serve;
 
 use clap::{Parser, Subcommand};
@@ -19,6 +20,8 @@ pub enum Commands {
     Account(account::AccountCommand),
     /// List available models
     Models(models::ModelsCommand),
+    /// Replay a captured request
+    Replay(replay::ReplayCommand),
     /// Start the API server
     Serve(serve::ServeCommand),
 }
@@ -1,5 +1,8 @@
 use clap::Args;
 
+use crate::accounts::{AccountManager, Provider};
+use crate::config::Config;
+use crate::http::HttpClients;
 use crate::providers::ModelRegistry;
 
 #[derive(Args)]
@@ -10,31 +13,53 @@ pub struct ModelsCommand {
 }
 
 pub async fn handle(cmd: ModelsCommand) -> anyhow::Result<()> {
+    let config = Config::load().await?;
+    let accounts = AccountManager::load(&config.credentials).await?;
+    let clients = HttpClients::new(&config.http)?;
+
     let registry = if cmd.refresh {
         println!("Refreshing model list...");
-        ModelRegistry::refresh().await?
+        ModelRegistry::load()?.refresh(&accounts, &clients).await?
     } else {
-        ModelRegistry::load()?
+        ModelRegistry::load_or_refresh(&config, &accounts, &clients).await?
     };
 
     println!("\nAvailable models:\n");
 
-    println!("Codex (OpenAI):");
-    for model in registry.codex_models() {
-        println!("  - {}", model.name);
-        if !model.reasoning_levels.is_empty() {
-            println!("    reasoning: {}", model.reasoning_levels.join(", "));
+    let listed = registry.list(&config, |_| true);
+
+    for (provider, title) in [
+        (Provider::Codex, "Codex (OpenAI)"),
+        (Provider::Claude, "Claude (Anthropic)"),
+        (Provider::Gemini, "Gemini (Google)"),
+    ] {
+        println!("{}:", title);
+        for model in registry.models(&provider) {
+            match model.context_length {
+                Some(context) => println!("  - {} ({}k context)", model.name, context / 1000),
+                None => println!("  - {}", model.name),
+            }
+            if let Some(pricing) = model.pricing {
+                println!("    price: ${} / ${} per 1M tokens", pricing.input, pricing.output);
+            }
+            if !model.reasoning_levels.is_empty() {
+                println!("    reasoning: {}", model.reasoning_levels.join(", "));
+            }
         }
+        println!();
     }
 
-    println!("\nClaude (Anthropic):");
-    for model in registry.claude_models() {
-        println!("  - {}", model.name);
+    let aliases: Vec<_> = listed.iter().filter(|m| m.alias).collect();
+    if !aliases.is_empty() {
+        println!("Aliases ([models] in config.toml):");
+        for model in aliases {
+            println!("  - {} -> {}", model.name, model.provider);
+        }
+        println!();
     }
 
-    println!("\nGemini (Google):");
-    for model in registry.gemini_models() {
-        println!("  - {}", model.name);
+    if let Some(fetched_at) = registry.fetched_at {
+        println!("Last refreshed: {}", fetched_at.format("%Y-%m-%d %H:%M"));
     }
 
     Ok(())
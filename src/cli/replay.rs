@@ -0,0 +1,118 @@
+use axum::body::Body;
+use axum::http::{header, Request};
+use clap::Args;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+use crate::accounts::{AccountManager, Provider};
+use crate::capture::{self, Capture};
+use crate::config::Config;
+use crate::http::HttpClients;
+use crate::providers::{self, ModelRegistry};
+
+#[derive(Args)]
+pub struct ReplayCommand {
+    /// Capture ID, from the x-omniproxy-capture-id response header
+    pub capture_id: String,
+
+    /// Send through this account (provider:name) instead of the next available one
+    #[arg(long)]
+    pub account: Option<String>,
+
+    /// Send to this model instead of the captured one
+    #[arg(long)]
+    pub model: Option<String>,
+}
+
+pub async fn handle(cmd: ReplayCommand) -> anyhow::Result<()> {
+    let config = Config::load().await?;
+    let accounts = AccountManager::load(&config.credentials).await?;
+    let clients = HttpClients::new(&config.http)?;
+
+    let source = Capture::open(&config.capture, &cmd.capture_id)?;
+    let inbound = source.read_json(capture::INBOUND_REQUEST)?;
+    let mut body = inbound
+        .get("body")
+        .filter(|b| b.is_object())
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Capture {} has no JSON request body", cmd.capture_id))?;
+
+    let model = match &cmd.model {
+        Some(model) => model.clone(),
+        None => body
+            .get("model")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Captured request has no 'model' field"))?
+            .to_string(),
+    };
+
+    let mut target = providers::resolve_model(&config, &model)?;
+    let provider = target.provider;
+
+    let registry = ModelRegistry::load()?;
+    let (base, effort) = providers::split_effort_suffix(&registry, &provider, &target.model);
+    target.model = base;
+    if let Some(effort) = effort {
+        body["reasoning_effort"] = Value::String(effort);
+    }
+    body["model"] = Value::String(target.model.clone());
+
+    let pinned = cmd.account.as_deref().or(target.account.as_deref());
+    let account = match pinned {
+        Some(id) => {
+            let (provider_name, name) = id.rsplit_once(':').unwrap_or((provider.as_str(), id));
+            if Provider::from_str(provider_name)? != provider {
+                anyhow::bail!("Account {} can't serve model '{}' ({})", id, model, provider);
+            }
+            accounts
+                .get(&provider, name)
+                .await
+                .ok_or_else(|| anyhow::anyhow!("Account not found: {}:{}", provider, name))?
+        }
+        None => accounts
+            .next_account(&provider)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("No valid accounts for provider: {}", provider))?,
+    };
+
+    println!(
+        "Replaying {} as '{}' on {} account '{}'",
+        cmd.capture_id, target.model, provider, account.name
+    );
+
+    let mut request = Request::post("/v1/chat/completions")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(&body)?))?;
+
+    // The replay is captured like any other request, so the two can be compared
+    let capture = if config.capture.enabled {
+        capture::prune(&config)?;
+        let capture = Capture::start(&config.capture).await?;
+        capture.write_json(capture::INBOUND_REQUEST, &inbound);
+        println!("Capture: {}", capture.id());
+        request.extensions_mut().insert(capture.clone());
+        Some(capture)
+    } else {
+        None
+    };
+
+    let client = clients.for_account(&account)?;
+    match providers::proxy_request(&client, &account, request, &config).await {
+        Ok(response) => {
+            println!("HTTP {}\n", response.status());
+            let bytes = response.into_body().collect().await?.to_bytes();
+            println!("{}", String::from_utf8_lossy(&bytes));
+        }
+        Err(e) => {
+            println!("HTTP {}\n", e.status());
+            println!("{}", e);
+        }
+    }
+
+    // Files are written in the background; finish before exiting
+    if let Some(capture) = capture {
+        capture.flush().await;
+    }
+
+    Ok(())
+}
@@ -1,13 +1,40 @@
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::accounts::Provider;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub server: ServerConfig,
     #[serde(default)]
     pub rotation: RotationConfig,
+    /// Model routing table, keyed by exact model name or glob pattern
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelRoute>,
+    /// Models tried in order when the requested one can't be served
+    #[serde(default)]
+    pub fallbacks: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub registry: RegistryConfig,
+    #[serde(default)]
+    pub translation: TranslationConfig,
+    #[serde(default)]
+    pub caching: CachingConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub credentials: CredentialsConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +49,253 @@ pub struct ServerConfig {
 pub struct RotationConfig {
     #[serde(default = "default_strategy")]
     pub strategy: String,
+    /// Seconds a rate-limited account is skipped when upstream sends no Retry-After
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryConfig {
+    /// Seconds before the cached model list (models.json) is fetched again
+    #[serde(default = "default_registry_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TranslationConfig {
+    /// Reject OpenAI parameters a provider can't honor instead of dropping them
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// Anthropic prompt caching breakpoints inserted automatically
+///
+/// ```toml
+/// [caching]
+/// system = true
+/// last_turns = 2
+///
+/// [caching.models."claude-3-5-haiku-*"]
+/// system = false
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachingConfig {
+    /// Policy for models without an override
+    #[serde(flatten)]
+    pub default: CachePolicy,
+    /// Per-model overrides, keyed by exact model name or glob pattern. Fields
+    /// an override leaves out keep their default.
+    #[serde(default)]
+    pub models: BTreeMap<String, CachePolicyOverride>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CachePolicy {
+    /// Cache the system prompt
+    #[serde(default)]
+    pub system: bool,
+    /// Cache tool definitions
+    #[serde(default)]
+    pub tools: bool,
+    /// Cache the conversation up to each of the last N turns
+    #[serde(default)]
+    pub last_turns: usize,
+}
+
+/// A `[caching.models]` entry; unset fields fall back to the default policy
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CachePolicyOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_turns: Option<usize>,
+}
+
+impl CachePolicyOverride {
+    /// `default` with this override's fields applied
+    pub fn apply(&self, default: CachePolicy) -> CachePolicy {
+        CachePolicy {
+            system: self.system.unwrap_or(default.system),
+            tools: self.tools.unwrap_or(default.tools),
+            last_turns: self.last_turns.unwrap_or(default.last_turns),
+        }
+    }
+}
+
+/// Outbound HTTP client settings, shared by upstream requests and token refresh
+///
+/// ```toml
+/// [http]
+/// connect_timeout_secs = 10
+/// proxy = "socks5://127.0.0.1:1080"
+///
+/// [http.providers.claude]
+/// proxy = "http://proxy.internal:3128"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpConfig {
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Longest wait for the next bytes of a response, so slow streams stay open while they make progress
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    /// Longest a whole request may take, including a streamed response; 0 (the
+    /// default) disables it, as long reasoning streams can run for many minutes
+    #[serde(default)]
+    pub timeout_secs: u64,
+    /// Negotiate HTTP/2 with providers that support it
+    #[serde(default = "default_true")]
+    pub http2: bool,
+    #[serde(default = "default_tcp_keepalive_secs")]
+    pub tcp_keepalive_secs: u64,
+    #[serde(default = "default_pool_idle_timeout_secs")]
+    pub pool_idle_timeout_secs: u64,
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+    /// Outbound proxy (`http://`, `https://` or `socks5://`) for all providers
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Per-provider overrides
+    #[serde(default)]
+    pub providers: HashMap<Provider, ProviderHttpConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderHttpConfig {
+    /// Outbound proxy for this provider, replacing `http.proxy`
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// API base URL replacing the provider's own, e.g. for a gateway in front of it
+    #[serde(default)]
+    pub base_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Level or `tracing` filter directives; `RUST_LOG` takes precedence
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Append logs to this file instead of stdout
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Keep request headers and message bodies out of logs
+    #[serde(default = "default_true")]
+    pub redact: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// OpenTelemetry span export
+///
+/// ```toml
+/// [telemetry]
+/// otlp_endpoint = "http://localhost:4318/v1/traces"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    /// OTLP/HTTP traces endpoint; spans are only exported when set
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Fraction of traces to sample, from 0.0 to 1.0
+    #[serde(default = "default_sample_ratio")]
+    pub sample_ratio: f64,
+}
+
+/// Debug capture of requests at each stage of translation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Where captures are written (default ~/.omniproxy/captures)
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    /// Captures kept; the oldest are removed first
+    #[serde(default = "default_max_captures")]
+    pub max_captures: usize,
+}
+
+/// Admin API on `/admin`, disabled until a token is set
+///
+/// ```toml
+/// [admin]
+/// token = "change-me"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdminConfig {
+    /// Bearer token required on admin requests; `OMNIPROXY_ADMIN_TOKEN` takes precedence
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl AdminConfig {
+    /// The admin token, or `None` when the admin API is disabled
+    pub fn token(&self) -> Option<String> {
+        std::env::var("OMNIPROXY_ADMIN_TOKEN")
+            .ok()
+            .or_else(|| self.token.clone())
+            .filter(|token| !token.is_empty())
+    }
+}
+
+/// Where account credentials are stored
+///
+/// ```toml
+/// [credentials]
+/// storage = "encrypted"
+/// key_file = "/etc/omniproxy/master.key"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CredentialsConfig {
+    #[serde(default)]
+    pub storage: CredentialStorage,
+    /// File holding the master key, read when `OMNIPROXY_MASTER_KEY` is unset
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialStorage {
+    /// Plaintext in accounts.json
+    #[default]
+    Plain,
+    /// Encrypted in accounts.json with a key derived from the master key
+    Encrypted,
+    /// In the OS keyring, with only account names in accounts.json
+    Keyring,
+}
+
+/// A `[models]` entry in config.toml
+///
+/// ```toml
+/// [models]
+/// fast = "gemini-2.5-flash"                                   # alias
+/// smart = { provider = "claude", model = "claude-opus-4-20250514" }
+/// "o4-*" = { provider = "codex" }                             # glob, model unchanged
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ModelRoute {
+    /// Another model name, resolved again through the routing table
+    Alias(String),
+    /// Explicit provider and optional upstream model id
+    Target {
+        provider: Provider,
+        #[serde(default)]
+        model: Option<String>,
+    },
 }
 
 fn default_host() -> String {
@@ -36,6 +310,54 @@ fn default_strategy() -> String {
     "round-robin".to_string()
 }
 
+fn default_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_registry_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_read_timeout_secs() -> u64 {
+    300
+}
+
+fn default_tcp_keepalive_secs() -> u64 {
+    60
+}
+
+fn default_pool_idle_timeout_secs() -> u64 {
+    90
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    32
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_service_name() -> String {
+    "omniproxy".to_string()
+}
+
+fn default_sample_ratio() -> f64 {
+    1.0
+}
+
+fn default_max_captures() -> usize {
+    100
+}
+
+fn default_true() -> bool {
+    true
+}
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
@@ -49,15 +371,72 @@ impl Default for RotationConfig {
     fn default() -> Self {
         Self {
             strategy: default_strategy(),
+            cooldown_secs: default_cooldown_secs(),
+        }
+    }
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: default_registry_ttl_secs(),
+        }
+    }
+}
+
+impl HttpConfig {
+    /// The provider's API base URL: its `base_url` override, or `default`
+    pub fn api_base<'a>(&'a self, provider: &Provider, default: &'a str) -> &'a str {
+        self.providers
+            .get(provider)
+            .and_then(|p| p.base_url.as_deref())
+            .map_or(default, |url| url.trim_end_matches('/'))
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: default_connect_timeout_secs(),
+            read_timeout_secs: default_read_timeout_secs(),
+            timeout_secs: 0,
+            http2: true,
+            tcp_keepalive_secs: default_tcp_keepalive_secs(),
+            pool_idle_timeout_secs: default_pool_idle_timeout_secs(),
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            proxy: None,
+            providers: HashMap::new(),
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            format: LogFormat::default(),
+            file: None,
+            redact: true,
+        }
+    }
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: default_service_name(),
+            sample_ratio: default_sample_ratio(),
         }
     }
 }
 
-impl Default for Config {
+impl Default for CaptureConfig {
     fn default() -> Self {
         Self {
-            server: ServerConfig::default(),
-            rotation: RotationConfig::default(),
+            enabled: false,
+            dir: None,
+            max_captures: default_max_captures(),
         }
     }
 }
@@ -106,3 +485,49 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_override_keeps_unset_fields_from_default() {
+        let caching: CachingConfig = toml::from_str(
+            r#"
+            system = true
+            tools = true
+            last_turns = 2
+
+            [models."claude-3-5-haiku-*"]
+            last_turns = 0
+            "#,
+        )
+        .unwrap();
+
+        let policy = caching.models["claude-3-5-haiku-*"].apply(caching.default);
+
+        assert!(policy.system);
+        assert!(policy.tools);
+        assert_eq!(policy.last_turns, 0);
+    }
+
+    #[test]
+    fn cache_override_can_turn_off_default() {
+        let caching: CachingConfig = toml::from_str(
+            r#"
+            system = true
+
+            [models.claude-opus-4]
+            system = false
+            tools = true
+            "#,
+        )
+        .unwrap();
+
+        let policy = caching.models["claude-opus-4"].apply(caching.default);
+
+        assert!(!policy.system);
+        assert!(policy.tools);
+        assert_eq!(policy.last_turns, 0);
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::accounts::{Account, Egress, Provider};
+use crate::config::HttpConfig;
+
+/// Upstream HTTP clients, built once and shared so connection pools and TLS
+/// sessions are reused across requests. Each provider gets its own client
+/// because it may egress through its own proxy, and accounts with their own
+/// egress settings get a client of their own.
+#[derive(Clone)]
+pub struct HttpClients {
+    config: HttpConfig,
+    providers: HashMap<Provider, reqwest::Client>,
+    // Per-account clients, with the egress settings they were built for
+    accounts: Arc<Mutex<HashMap<(Provider, String), AccountClient>>>,
+}
+
+type AccountClient = (Egress, reqwest::Client);
+
+impl HttpClients {
+    pub fn new(config: &HttpConfig) -> anyhow::Result<Self> {
+        let mut providers = HashMap::new();
+
+        for provider in [Provider::Codex, Provider::Claude, Provider::Gemini] {
+            let proxy = provider_proxy(config, &provider);
+            providers.insert(provider, build_client(config, proxy, &Egress::default())?);
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            providers,
+            accounts: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// The settings the clients were built with
+    pub fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+
+    /// Client for a provider
+    pub fn for_provider(&self, provider: &Provider) -> &reqwest::Client {
+        &self.providers[provider]
+    }
+
+    /// Client for requests made on behalf of an account, honoring its egress settings
+    pub fn for_account(&self, account: &Account) -> anyhow::Result<reqwest::Client> {
+        self.for_egress(&account.provider, &account.name, &account.egress)
+    }
+
+    /// Client for an account's provider and egress settings
+    pub fn for_egress(&self, provider: &Provider, name: &str, egress: &Egress) -> anyhow::Result<reqwest::Client> {
+        if egress.is_default() {
+            return Ok(self.for_provider(provider).clone());
+        }
+
+        let mut accounts = self.accounts.lock().unwrap();
+        let key = (*provider, name.to_string());
+
+        // Rebuild when the account's settings changed since the client was made
+        if let Some((built_for, client)) = accounts.get(&key) {
+            if built_for == egress {
+                return Ok(client.clone());
+            }
+        }
+
+        let proxy = egress.proxy.as_deref().or(provider_proxy(&self.config, provider));
+        let client = build_client(&self.config, proxy, egress)
+            .map_err(|e| anyhow::anyhow!("Invalid egress settings for {}:{}: {}", provider, name, e))?;
+        accounts.insert(key, (egress.clone(), client.clone()));
+
+        Ok(client)
+    }
+}
+
+/// The provider's proxy override, or the global one
+fn provider_proxy<'a>(config: &'a HttpConfig, provider: &Provider) -> Option<&'a str> {
+    config
+        .providers
+        .get(provider)
+        .and_then(|p| p.proxy.as_deref())
+        .or(config.proxy.as_deref())
+}
+
+fn build_client(config: &HttpConfig, proxy: Option<&str>, egress: &Egress) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(config.read_timeout_secs))
+        .tcp_keepalive(Duration::from_secs(config.tcp_keepalive_secs))
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_secs))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host);
+
+    if config.timeout_secs > 0 {
+        builder = builder.timeout(Duration::from_secs(config.timeout_secs));
+    }
+
+    builder = if config.http2 {
+        builder
+            .http2_adaptive_window(true)
+            .http2_keep_alive_interval(Duration::from_secs(config.tcp_keepalive_secs))
+            .http2_keep_alive_while_idle(true)
+    } else {
+        builder.http1_only()
+    };
+
+    if let Some(proxy) = proxy {
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| anyhow::anyhow!("Invalid proxy URL '{}': {}", proxy, e))?;
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(address) = egress.bind_address {
+        builder = builder.local_address(address);
+    }
+
+    if let Some(user_agent) = &egress.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+
+    Ok(builder.build()?)
+}
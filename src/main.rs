@@ -1,24 +1,36 @@
 mod accounts;
 mod auth;
+mod capture;
 mod cli;
 mod config;
+mod http;
+mod logging;
+mod metrics;
 mod providers;
 mod server;
 
 use clap::Parser;
 use cli::{Cli, Commands};
+use config::Config;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-
     let cli = Cli::parse();
 
+    let config = Config::load().await?;
+    let tracer_provider = logging::init(&config.logging, &config.telemetry)?;
+
     match cli.command {
         Commands::Account(cmd) => cli::account::handle(cmd).await?,
         Commands::Models(cmd) => cli::models::handle(cmd).await?,
+        Commands::Replay(cmd) => cli::replay::handle(cmd).await?,
         Commands::Serve(cmd) => cli::serve::handle(cmd).await?,
     }
 
+    // Flush spans still waiting to be exported
+    if let Some(provider) = tracer_provider {
+        provider.shutdown()?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,187 @@
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sha2::{Digest, Sha256};
+
+use crate::accounts::{AccountManager, Provider};
+use crate::server::Completion;
+
+/// Latency buckets in seconds, from a fast cache hit to a long reasoning stream
+const LATENCY_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+/// Prometheus metrics served on `GET /metrics`
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    time_to_first_token: HistogramVec,
+    tokens: IntCounterVec,
+    upstream_responses: IntCounterVec,
+    failovers: IntCounterVec,
+    account_available: IntGaugeVec,
+    account_cooling_down: IntGaugeVec,
+    account_token_expiry: GaugeVec,
+}
+
+/// Model label of requests for a model the registry doesn't know
+pub const UNKNOWN_MODEL: &str = "unknown";
+
+/// Who served a request, for labelling
+#[derive(Debug, Clone)]
+pub struct RequestLabels {
+    pub provider: String,
+    pub model: String,
+    pub account: String,
+    pub client: String,
+}
+
+impl Metrics {
+    pub fn new() -> anyhow::Result<Self> {
+        let registry = Registry::new_custom(Some("omniproxy".to_string()), None)?;
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Chat completion requests by outcome"),
+            &["provider", "model", "account", "client", "status"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "Time until the response finished streaming")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["provider", "model", "account"],
+        )?;
+        let time_to_first_token = HistogramVec::new(
+            HistogramOpts::new("time_to_first_token_seconds", "Time until the first response bytes")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["provider", "model", "account"],
+        )?;
+        let tokens = IntCounterVec::new(
+            Opts::new("tokens_total", "Tokens reported by upstream usage"),
+            &["provider", "model", "account", "client", "type"],
+        )?;
+        let upstream_responses = IntCounterVec::new(
+            Opts::new("upstream_responses_total", "Upstream responses by status code or transport error"),
+            &["provider", "account", "status"],
+        )?;
+        let failovers = IntCounterVec::new(
+            Opts::new("failovers_total", "Candidates abandoned for the next account or fallback model"),
+            &["provider", "model", "reason"],
+        )?;
+        let account_available = IntGaugeVec::new(
+            Opts::new("account_available", "1 when the account can take new requests"),
+            &["provider", "account"],
+        )?;
+        let account_cooling_down = IntGaugeVec::new(
+            Opts::new("account_cooling_down", "1 while the account is rate limited"),
+            &["provider", "account"],
+        )?;
+        let account_token_expiry = GaugeVec::new(
+            Opts::new("account_token_expiry_seconds", "Seconds until the account's access token expires"),
+            &["provider", "account"],
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(time_to_first_token.clone()))?;
+        registry.register(Box::new(tokens.clone()))?;
+        registry.register(Box::new(upstream_responses.clone()))?;
+        registry.register(Box::new(failovers.clone()))?;
+        registry.register(Box::new(account_available.clone()))?;
+        registry.register(Box::new(account_cooling_down.clone()))?;
+        registry.register(Box::new(account_token_expiry.clone()))?;
+
+        Ok(Self {
+            registry,
+            requests,
+            request_duration,
+            time_to_first_token,
+            tokens,
+            upstream_responses,
+            failovers,
+            account_available,
+            account_cooling_down,
+            account_token_expiry,
+        })
+    }
+
+    /// Render all metrics in the Prometheus text format, refreshing account gauges first
+    pub async fn render(&self, accounts: &AccountManager) -> anyhow::Result<String> {
+        self.account_available.reset();
+        self.account_cooling_down.reset();
+        self.account_token_expiry.reset();
+
+        for provider in [Provider::Codex, Provider::Claude, Provider::Gemini] {
+            for account in accounts.list(&provider).await {
+                let labels = [provider.as_str(), account.name.as_str()];
+                let cooling_down = accounts.is_cooling_down(&account);
+                let expires_in = (account.credentials.expires_at - chrono::Utc::now()).num_seconds();
+
+                self.account_available
+                    .with_label_values(&labels)
+                    .set(accounts.is_available(&account) as i64);
+                self.account_cooling_down.with_label_values(&labels).set(cooling_down as i64);
+                self.account_token_expiry.with_label_values(&labels).set(expires_in as f64);
+            }
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// Count a finished request
+    pub fn record_request(&self, labels: &RequestLabels, status: u16) {
+        self.requests
+            .with_label_values(&[&labels.provider, &labels.model, &labels.account, &labels.client, &status.to_string()])
+            .inc();
+    }
+
+    /// Count an upstream response; `status` is a status code or an error class such as `timeout`
+    pub fn record_upstream(&self, provider: &Provider, account: &str, status: &str) {
+        self.upstream_responses
+            .with_label_values(&[provider.as_str(), account, status])
+            .inc();
+    }
+
+    pub fn record_failover(&self, provider: &Provider, model: &str, reason: &str) {
+        self.failovers.with_label_values(&[provider.as_str(), model, reason]).inc();
+    }
+
+    /// Record latency and token usage once a response has been sent
+    pub fn record_completion(&self, labels: &RequestLabels, completion: &Completion) {
+        let latency_labels = [labels.provider.as_str(), labels.model.as_str(), labels.account.as_str()];
+
+        if let Some(ttft) = completion.time_to_first_token {
+            self.time_to_first_token
+                .with_label_values(&latency_labels)
+                .observe(ttft.as_secs_f64());
+        }
+        self.request_duration
+            .with_label_values(&latency_labels)
+            .observe(completion.duration.as_secs_f64());
+
+        for (tokens, kind) in [(completion.prompt_tokens(), "prompt"), (completion.completion_tokens(), "completion")] {
+            if let Some(count) = tokens {
+                self.tokens
+                    .with_label_values(&[&labels.provider, &labels.model, &labels.account, &labels.client, kind])
+                    .inc_by(count);
+            }
+        }
+    }
+}
+
+/// Short fingerprint of the client's API key, so clients can be told apart
+/// without exposing their keys
+pub fn client_key(headers: &axum::http::HeaderMap) -> String {
+    let key = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.strip_prefix("Bearer ").unwrap_or(v))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()));
+
+    match key {
+        Some(key) if !key.is_empty() => {
+            let digest = Sha256::digest(key.as_bytes());
+            digest[..4].iter().map(|b| format!("{:02x}", b)).collect()
+        }
+        _ => "none".to_string(),
+    }
+}
@@ -1,18 +1,42 @@
 use axum::body::Body;
-use axum::http::{Request, Response, StatusCode};
+use axum::http::{Request, Response};
 use http_body_util::BodyExt;
 use serde_json::Value;
 
-use crate::accounts::Account;
+use super::structured::ResponseFormat;
+use super::messages::{self, Conversation, Part, PartKind, Role};
+use super::{errors, params, reasoning, routing, stream, ModelInfo, ProxyError};
+use crate::accounts::{Account, Provider};
+use crate::capture::{self, Capture};
+use crate::config::{CachePolicy, Config, HttpConfig};
 
 const API_BASE: &str = "https://api.anthropic.com/v1";
 
+/// Tool forced for `response_format`, whose input is the structured answer
+const JSON_TOOL_NAME: &str = "json_response";
+
+/// Anthropic's limit on `cache_control` breakpoints per request
+const MAX_CACHE_BREAKPOINTS: usize = 4;
+
+/// User turn inserted before a conversation that opens with an assistant turn
+const LEADING_USER_TEXT: &str = "Continue.";
+
 pub struct ClaudeProvider;
 
 impl ClaudeProvider {
-    pub async fn proxy(account: &Account, request: Request<Body>) -> anyhow::Result<Response<Body>> {
-        let client = reqwest::Client::new();
-
+    /// OpenAI parameters translated by `convert_request`
+    pub const SUPPORTED_PARAMS: &'static [&'static str] = &[
+        "max_tokens",
+        "max_completion_tokens",
+        "temperature",
+        "top_p",
+        "stop",
+        "user",
+        "reasoning_effort",
+        "response_format",
+    ];
+
+    pub async fn proxy(client: &reqwest::Client, account: &Account, request: Request<Body>, config: &Config) -> Result<Response<Body>, ProxyError> {
         // Get request body
         let (parts, body) = request.into_parts();
         let path = parts.uri.path().to_string();
@@ -20,14 +44,35 @@ impl ClaudeProvider {
 
         // For Claude, we need to convert OpenAI format to Anthropic format
         // if the request is to /chat/completions
-        let (url, body_bytes) = if path == "/v1/chat/completions" || path == "/chat/completions" || path == "chat/completions" {
-            let body_json: Value = serde_json::from_slice(&body_bytes)?;
-            let converted = Self::convert_request(body_json)?;
-            (format!("{}/messages", API_BASE), serde_json::to_vec(&converted)?)
+        let api_base = config.http.api_base(&Provider::Claude, API_BASE);
+        let is_chat = path == "/v1/chat/completions" || path == "/chat/completions" || path == "chat/completions";
+        let mut streaming = false;
+        let mut include_usage = false;
+        let mut json_mode = false;
+        let (url, body_bytes) = if is_chat {
+            let body_json: Value = serde_json::from_slice(&body_bytes)
+            .map_err(|e| ProxyError::InvalidRequest(format!("Invalid JSON: {}", e)))?;
+            streaming = body_json.get("stream").and_then(|s| s.as_bool()).unwrap_or(false);
+            json_mode = ResponseFormat::from_request(&body_json)?.is_some();
+            include_usage = body_json
+                .pointer("/stream_options/include_usage")
+                .and_then(|u| u.as_bool())
+                .unwrap_or(false);
+            let model = body_json.get("model").and_then(|m| m.as_str()).unwrap_or_default();
+            let cache_policy = routing::match_model_key(&config.caching.models, model)
+                .map(|o| o.apply(config.caching.default))
+                .unwrap_or(config.caching.default);
+            let converted = Self::convert_request(body_json, &cache_policy)?;
+            (format!("{}/messages", api_base), serde_json::to_vec(&converted).map_err(|e| ProxyError::Internal(e.to_string()))?)
         } else {
-            (format!("{}{}", API_BASE, path), body_bytes.to_vec())
+            (format!("{}{}", api_base, path), body_bytes.to_vec())
         };
 
+        let capture = parts.extensions.get::<Capture>().cloned();
+        if let Some(capture) = &capture {
+            capture.write_request(capture::UPSTREAM_REQUEST, parts.method.as_str(), &url, None, &body_bytes);
+        }
+
         // Build proxied request
         let mut req_builder = client
             .request(parts.method.clone(), &url)
@@ -35,9 +80,14 @@ impl ClaudeProvider {
             .header("anthropic-version", "2023-06-01")
             .header("Content-Type", "application/json");
 
-        // Copy relevant headers
+        // Copy relevant headers, keeping the account's own User-Agent if it has one
+        let keep_user_agent = account.egress.user_agent.is_some();
         for (name, value) in parts.headers.iter() {
-            if name != "host" && name != "authorization" && name != "content-length" {
+            let skip = name == "host"
+                || name == "authorization"
+                || name == "content-length"
+                || (keep_user_agent && name == "user-agent");
+            if !skip {
                 req_builder = req_builder.header(name.clone(), value.clone());
             }
         }
@@ -47,9 +97,12 @@ impl ClaudeProvider {
             .send()
             .await?;
 
+        if !response.status().is_success() {
+            return Err(ProxyError::from_upstream(Provider::Claude, response).await);
+        }
+
         // Build response
-        let status = StatusCode::from_u16(response.status().as_u16())?;
-        let mut builder = Response::builder().status(status);
+        let mut builder = Response::builder().status(response.status());
 
         for (name, value) in response.headers() {
             if name != "transfer-encoding" && name != "content-length" {
@@ -57,12 +110,22 @@ impl ClaudeProvider {
             }
         }
 
+        // Translate Anthropic stream events into OpenAI chunks as they arrive
+        if is_chat && streaming {
+            let body = stream::translate_sse(response, Self::stream_translator(include_usage, json_mode), capture);
+            return Ok(builder.body(body)?);
+        }
+
+        let stage = capture::response_stage(capture::UPSTREAM_RESPONSE, response.headers());
         let body = response.bytes().await?;
+        if let Some(capture) = &capture {
+            capture.write(&stage, &body);
+        }
 
         // Convert Anthropic response to OpenAI format
-        let converted_body = if path == "/v1/chat/completions" || path == "/chat/completions" || path == "chat/completions" {
+        let converted_body = if is_chat {
             if let Ok(anthropic_response) = serde_json::from_slice::<Value>(&body) {
-                serde_json::to_vec(&Self::convert_response(anthropic_response)?)?
+                serde_json::to_vec(&Self::convert_response(anthropic_response, json_mode)?).map_err(|e| ProxyError::Translation(e.to_string()))?
             } else {
                 body.to_vec()
             }
@@ -75,8 +138,38 @@ impl ClaudeProvider {
         Ok(response)
     }
 
+    /// List models available to the account
+    pub async fn list_models(client: &reqwest::Client, account: &Account, http: &HttpConfig) -> anyhow::Result<Vec<ModelInfo>> {
+        let response = client
+            .get(format!("{}/models", http.api_base(&Provider::Claude, API_BASE)))
+            .header("x-api-key", &account.credentials.access_token)
+            .header("anthropic-version", "2023-06-01")
+            .query(&[("limit", "1000")])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let body: Value = response.json().await?;
+        let models = body
+            .get("data")
+            .and_then(|d| d.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|m| m.get("id").and_then(|i| i.as_str()))
+                    .map(|id| ModelInfo {
+                        name: id.to_string(),
+                        ..Default::default()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(models)
+    }
+
     /// Convert OpenAI chat completion request to Anthropic messages format
-    fn convert_request(openai_req: Value) -> anyhow::Result<Value> {
+    #[tracing::instrument(name = "translate_request", skip_all)]
+    fn convert_request(openai_req: Value, cache_policy: &CachePolicy) -> Result<Value, ProxyError> {
         let mut anthropic_req = serde_json::json!({});
 
         // Model
@@ -85,37 +178,51 @@ impl ClaudeProvider {
         }
 
         // Messages
-        if let Some(messages) = openai_req.get("messages").and_then(|m| m.as_array()) {
-            let mut anthropic_messages = Vec::new();
-            let mut system_prompt = None;
-
-            for msg in messages {
-                let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or("user");
-                let content = msg.get("content").cloned().unwrap_or(Value::String(String::new()));
-
-                if role == "system" {
-                    system_prompt = Some(content);
-                } else {
-                    let anthropic_role = match role {
-                        "assistant" => "assistant",
-                        _ => "user",
-                    };
-                    anthropic_messages.push(serde_json::json!({
-                        "role": anthropic_role,
-                        "content": content,
-                    }));
+        let conversation = Conversation::from_request(&openai_req);
+        let mut anthropic_messages: Vec<Value> = conversation
+            .turns
+            .iter()
+            .map(|turn| {
+                let role = match turn.role {
+                    Role::User => "user",
+                    Role::Assistant => "assistant",
+                };
+                serde_json::json!({
+                    "role": role,
+                    "content": turn.parts.iter().map(Self::convert_part).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+
+        // Anthropic requires the conversation to open with a user turn
+        if conversation.turns.first().is_some_and(|t| t.role == Role::Assistant) {
+            anthropic_messages.insert(0, serde_json::json!({
+                "role": "user",
+                "content": [{ "type": "text", "text": LEADING_USER_TEXT }],
+            }));
+        }
+
+        // A trailing assistant turn is a prefill the reply continues from;
+        // Anthropic rejects trailing whitespace in it
+        if conversation.turns.last().is_some_and(|t| t.role == Role::Assistant) {
+            if let Some(Value::Array(blocks)) = anthropic_messages.last_mut().map(|m| &mut m["content"]) {
+                if let Some(text) = blocks.last_mut().and_then(|b| b.get_mut("text")) {
+                    *text = Value::String(text.as_str().unwrap_or_default().trim_end().to_string());
                 }
             }
+        }
 
-            anthropic_req["messages"] = Value::Array(anthropic_messages);
+        anthropic_req["messages"] = Value::Array(anthropic_messages);
 
-            if let Some(system) = system_prompt {
-                anthropic_req["system"] = system;
-            }
+        // Keep system parts as blocks when they carry cache_control
+        if conversation.system.iter().any(|p| p.cache_control.is_some()) {
+            anthropic_req["system"] = Value::Array(conversation.system.iter().map(Self::convert_part).collect());
+        } else if let Some(system) = conversation.system_prompt() {
+            anthropic_req["system"] = Value::String(system);
         }
 
         // Max tokens
-        if let Some(max_tokens) = openai_req.get("max_tokens") {
+        if let Some(max_tokens) = params::max_output_tokens(&openai_req) {
             anthropic_req["max_tokens"] = max_tokens.clone();
         } else {
             anthropic_req["max_tokens"] = Value::Number(4096.into());
@@ -126,23 +233,189 @@ impl ClaudeProvider {
             anthropic_req["temperature"] = temp.clone();
         }
 
+        // Sampling
+        if let Some(top_p) = openai_req.get("top_p").filter(|v| !v.is_null()) {
+            anthropic_req["top_p"] = top_p.clone();
+        }
+
+        if let Some(stop) = params::stop_sequences(&openai_req) {
+            anthropic_req["stop_sequences"] = stop;
+        }
+
+        // End-user id for abuse detection
+        if let Some(user) = openai_req.get("user").and_then(|u| u.as_str()) {
+            anthropic_req["metadata"] = serde_json::json!({ "user_id": user });
+        }
+
+        // Extended thinking from reasoning_effort
+        if let Some(effort) = reasoning::requested_effort(&openai_req) {
+            let budget = reasoning::thinking_budget(effort)
+                .ok_or_else(|| ProxyError::InvalidRequest(format!("Unsupported reasoning_effort: {}", effort)))?;
+
+            // budget_tokens counts against max_tokens and must stay below it
+            let max_tokens = anthropic_req["max_tokens"].as_u64().unwrap_or(4096);
+            if max_tokens <= budget as u64 {
+                anthropic_req["max_tokens"] = Value::from(max_tokens + budget as u64);
+            }
+
+            anthropic_req["thinking"] = serde_json::json!({
+                "type": "enabled",
+                "budget_tokens": budget,
+            });
+
+            if conversation.turns.last().is_some_and(|t| t.role == Role::Assistant) {
+                return Err(ProxyError::InvalidRequest(
+                    "Assistant prefill can't be combined with reasoning_effort on Claude".into(),
+                ));
+            }
+
+            // Thinking requires the default temperature
+            if let Some(obj) = anthropic_req.as_object_mut() {
+                obj.remove("temperature");
+            }
+        }
+
+        // Structured output: force a tool whose input is the JSON answer
+        if let Some(format) = ResponseFormat::from_request(&openai_req)? {
+            if anthropic_req.get("thinking").is_some() {
+                return Err(ProxyError::InvalidRequest(
+                    "response_format can't be combined with reasoning_effort on Claude".into(),
+                ));
+            }
+
+            let description = match &format {
+                ResponseFormat::JsonSchema { name, description, .. } => description
+                    .clone()
+                    .unwrap_or_else(|| format!("Respond with a {} JSON object", name)),
+                ResponseFormat::JsonObject => "Respond with a JSON object".to_string(),
+            };
+
+            anthropic_req["tools"] = serde_json::json!([{
+                "name": JSON_TOOL_NAME,
+                "description": description,
+                "input_schema": format.schema(),
+            }]);
+            anthropic_req["tool_choice"] = serde_json::json!({
+                "type": "tool",
+                "name": JSON_TOOL_NAME,
+            });
+        }
+
         // Stream
         if let Some(stream) = openai_req.get("stream") {
             anthropic_req["stream"] = stream.clone();
         }
 
+        Self::add_cache_breakpoints(&mut anthropic_req, cache_policy);
+
         Ok(anthropic_req)
     }
 
+    /// Mark the configured prefixes (tools, system prompt, last N turns) with
+    /// `cache_control`, most stable first, without exceeding Anthropic's
+    /// limit of breakpoints per request. Breakpoints set by the client count
+    /// towards the limit and are kept.
+    fn add_cache_breakpoints(req: &mut Value, policy: &CachePolicy) {
+        let ephemeral = serde_json::json!({ "type": "ephemeral" });
+
+        let explicit = ["tools", "system", "messages"]
+            .iter()
+            .map(|key| Self::count_cache_control(&req[*key]))
+            .sum::<usize>();
+        let mut remaining = MAX_CACHE_BREAKPOINTS.saturating_sub(explicit);
+
+        if policy.tools && remaining > 0 {
+            if let Some(tool) = req.get_mut("tools").and_then(|t| t.as_array_mut()).and_then(|t| t.last_mut()) {
+                if tool.get("cache_control").is_none() {
+                    tool["cache_control"] = ephemeral.clone();
+                    remaining -= 1;
+                }
+            }
+        }
+
+        if policy.system && remaining > 0 {
+            if let Some(Value::String(text)) = req.get("system") {
+                req["system"] = serde_json::json!([{ "type": "text", "text": text }]);
+            }
+            if let Some(block) = req.get_mut("system").and_then(|s| s.as_array_mut()).and_then(|s| s.last_mut()) {
+                if block.get("cache_control").is_none() {
+                    block["cache_control"] = ephemeral.clone();
+                    remaining -= 1;
+                }
+            }
+        }
+
+        if let Some(messages) = req.get_mut("messages").and_then(|m| m.as_array_mut()) {
+            for message in messages.iter_mut().rev().take(policy.last_turns) {
+                if remaining == 0 {
+                    break;
+                }
+                if let Some(block) = message["content"].as_array_mut().and_then(|c| c.last_mut()) {
+                    if block.get("cache_control").is_none() {
+                        block["cache_control"] = ephemeral.clone();
+                        remaining -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn count_cache_control(value: &Value) -> usize {
+        match value {
+            Value::Array(items) => items.iter().map(Self::count_cache_control).sum(),
+            Value::Object(obj) => {
+                usize::from(obj.contains_key("cache_control"))
+                    + obj.get("content").map(Self::count_cache_control).unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Convert a message part to an Anthropic content block
+    fn convert_part(part: &Part) -> Value {
+        let mut block = match &part.kind {
+            PartKind::Text(text) => serde_json::json!({ "type": "text", "text": text }),
+            PartKind::Image { url } => match messages::parse_data_url(url) {
+                Some((media_type, data)) => serde_json::json!({
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": media_type, "data": data },
+                }),
+                None => serde_json::json!({
+                    "type": "image",
+                    "source": { "type": "url", "url": url },
+                }),
+            },
+        };
+
+        if let Some(cache_control) = &part.cache_control {
+            block["cache_control"] = cache_control.clone();
+        }
+
+        block
+    }
+
     /// Convert Anthropic response to OpenAI format
-    fn convert_response(anthropic_resp: Value) -> anyhow::Result<Value> {
-        let content = anthropic_resp
+    ///
+    /// In JSON mode the forced tool's input is returned as the message content.
+    #[tracing::instrument(name = "translate_response", skip_all)]
+    fn convert_response(anthropic_resp: Value, json_mode: bool) -> Result<Value, ProxyError> {
+        let blocks = anthropic_resp
             .get("content")
             .and_then(|c| c.as_array())
-            .and_then(|arr| arr.first())
-            .and_then(|block| block.get("text"))
-            .and_then(|t| t.as_str())
-            .unwrap_or("");
+            .map(|arr| arr.as_slice())
+            .unwrap_or_default();
+
+        let content = if json_mode {
+            blocks
+                .iter()
+                .find(|b| b.get("type").and_then(|t| t.as_str()) == Some("tool_use"))
+                .and_then(|b| b.get("input"))
+                .map(|input| input.to_string())
+                .unwrap_or_default()
+        } else {
+            Self::join_blocks(blocks, "text", "text")
+        };
+        let thinking = Self::join_blocks(blocks, "thinking", "thinking");
 
         let model = anthropic_resp
             .get("model")
@@ -154,9 +427,18 @@ impl ClaudeProvider {
             .and_then(|i| i.as_str())
             .unwrap_or("msg_unknown");
 
-        let usage = anthropic_resp.get("usage");
-        let input_tokens = usage.and_then(|u| u.get("input_tokens")).and_then(|t| t.as_i64()).unwrap_or(0);
-        let output_tokens = usage.and_then(|u| u.get("output_tokens")).and_then(|t| t.as_i64()).unwrap_or(0);
+        let finish_reason = Self::finish_reason(anthropic_resp.get("stop_reason").and_then(|r| r.as_str()), json_mode);
+
+        let usage = anthropic_resp.get("usage").unwrap_or(&Value::Null);
+        let output_tokens = usage.get("output_tokens").and_then(|t| t.as_i64()).unwrap_or(0);
+
+        let mut message = serde_json::json!({
+            "role": "assistant",
+            "content": content,
+        });
+        if !thinking.is_empty() {
+            message["reasoning_content"] = Value::String(thinking);
+        }
 
         let openai_response = serde_json::json!({
             "id": format!("chatcmpl-{}", id),
@@ -165,19 +447,96 @@ impl ClaudeProvider {
             "model": model,
             "choices": [{
                 "index": 0,
-                "message": {
-                    "role": "assistant",
-                    "content": content,
-                },
-                "finish_reason": "stop",
+                "message": message,
+                "finish_reason": finish_reason,
             }],
-            "usage": {
-                "prompt_tokens": input_tokens,
-                "completion_tokens": output_tokens,
-                "total_tokens": input_tokens + output_tokens,
-            }
+            "usage": Self::convert_usage(usage, output_tokens),
         });
 
         Ok(openai_response)
     }
+
+    /// Build a translator from Anthropic stream events to OpenAI chunks
+    fn stream_translator(include_usage: bool, json_mode: bool) -> impl FnMut(Value) -> Vec<Value> + Send + 'static {
+        let created = chrono::Utc::now().timestamp();
+        let mut id = String::from("chatcmpl-unknown");
+        let mut model = String::from("claude");
+        let mut input_usage = Value::Null;
+
+        move |event| {
+            let delta = match event.get("type").and_then(|t| t.as_str()) {
+                Some("message_start") => {
+                    let message = &event["message"];
+                    if let Some(msg_id) = message.get("id").and_then(|i| i.as_str()) {
+                        id = format!("chatcmpl-{}", msg_id);
+                    }
+                    if let Some(name) = message.get("model").and_then(|m| m.as_str()) {
+                        model = name.to_string();
+                    }
+                    input_usage = message.get("usage").cloned().unwrap_or_default();
+                    serde_json::json!({ "role": "assistant", "content": "" })
+                }
+                Some("content_block_delta") => match event.pointer("/delta/type").and_then(|t| t.as_str()) {
+                    Some("text_delta") => serde_json::json!({ "content": event["delta"]["text"] }),
+                    Some("thinking_delta") => serde_json::json!({ "reasoning_content": event["delta"]["thinking"] }),
+                    Some("input_json_delta") if json_mode => serde_json::json!({ "content": event["delta"]["partial_json"] }),
+                    _ => return vec![],
+                },
+                Some("message_delta") => {
+                    let finish_reason = Self::finish_reason(event.pointer("/delta/stop_reason").and_then(|r| r.as_str()), json_mode);
+                    let mut chunk = stream::chunk(&id, &model, created, serde_json::json!({}), Some(finish_reason));
+                    if include_usage {
+                        let output_tokens = event.pointer("/usage/output_tokens").and_then(|t| t.as_i64()).unwrap_or(0);
+                        chunk["usage"] = Self::convert_usage(&input_usage, output_tokens);
+                    }
+                    return vec![chunk];
+                }
+                // Mid-stream errors are passed on for the client to see
+                Some("error") => return vec![errors::normalize_stream_error(Provider::Claude, &event)],
+                _ => return vec![],
+            };
+
+            vec![stream::chunk(&id, &model, created, delta, None)]
+        }
+    }
+
+    /// Convert Anthropic usage to OpenAI usage.
+    ///
+    /// Anthropic's `input_tokens` excludes cache reads and writes, while
+    /// OpenAI's `prompt_tokens` includes cached tokens, so they are added back.
+    fn convert_usage(usage: &Value, output_tokens: i64) -> Value {
+        let count = |key: &str| usage.get(key).and_then(|t| t.as_i64()).unwrap_or(0);
+        let cache_read = count("cache_read_input_tokens");
+        let cache_creation = count("cache_creation_input_tokens");
+        let prompt_tokens = count("input_tokens") + cache_read + cache_creation;
+
+        serde_json::json!({
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": output_tokens,
+            "total_tokens": prompt_tokens + output_tokens,
+            "prompt_tokens_details": {
+                "cached_tokens": cache_read,
+                "cache_creation_tokens": cache_creation,
+            },
+        })
+    }
+
+    /// Concatenate the `field` of all content blocks of the given type
+    fn join_blocks(blocks: &[Value], block_type: &str, field: &str) -> String {
+        blocks
+            .iter()
+            .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some(block_type))
+            .filter_map(|b| b.get(field).and_then(|t| t.as_str()))
+            .collect()
+    }
+
+    /// Map an Anthropic stop reason to an OpenAI finish reason
+    fn finish_reason(stop_reason: Option<&str>, json_mode: bool) -> &'static str {
+        match stop_reason {
+            Some("max_tokens") => "length",
+            // The forced JSON tool is an implementation detail, not a tool call
+            Some("tool_use") if !json_mode => "tool_calls",
+            _ => "stop",
+        }
+    }
 }
@@ -1,33 +1,45 @@
 use axum::body::Body;
-use axum::http::{Request, Response, StatusCode};
+use axum::http::{Request, Response};
 use http_body_util::BodyExt;
+use serde_json::Value;
 
-use crate::accounts::Account;
+use super::{ModelInfo, ProxyError};
+use crate::accounts::{Account, Provider};
+use crate::capture::{self, Capture};
+use crate::config::{Config, HttpConfig};
 
 const API_BASE: &str = "https://api.openai.com/v1";
 
 pub struct CodexProvider;
 
 impl CodexProvider {
-    pub async fn proxy(account: &Account, request: Request<Body>) -> anyhow::Result<Response<Body>> {
-        let client = reqwest::Client::new();
-
+    pub async fn proxy(client: &reqwest::Client, account: &Account, request: Request<Body>, config: &Config) -> Result<Response<Body>, ProxyError> {
         let path = request.uri().path();
-        let url = format!("{}{}", API_BASE, path);
+        let url = format!("{}{}", config.http.api_base(&Provider::Codex, API_BASE), path);
 
         // Get request body
         let (parts, body) = request.into_parts();
         let body_bytes = body.collect().await?.to_bytes();
 
+        let capture = parts.extensions.get::<Capture>().cloned();
+        if let Some(capture) = &capture {
+            capture.write_request(capture::UPSTREAM_REQUEST, parts.method.as_str(), &url, None, &body_bytes);
+        }
+
         // Build proxied request
         let mut req_builder = client
             .request(parts.method.clone(), &url)
             .header("Authorization", format!("Bearer {}", account.credentials.access_token))
             .header("Content-Type", "application/json");
 
-        // Copy relevant headers
+        // Copy relevant headers, keeping the account's own User-Agent if it has one
+        let keep_user_agent = account.egress.user_agent.is_some();
         for (name, value) in parts.headers.iter() {
-            if name != "host" && name != "authorization" && name != "content-length" {
+            let skip = name == "host"
+                || name == "authorization"
+                || name == "content-length"
+                || (keep_user_agent && name == "user-agent");
+            if !skip {
                 req_builder = req_builder.header(name.clone(), value.clone());
             }
         }
@@ -37,9 +49,12 @@ impl CodexProvider {
             .send()
             .await?;
 
+        if !response.status().is_success() {
+            return Err(ProxyError::from_upstream(Provider::Codex, response).await);
+        }
+
         // Build response
-        let status = StatusCode::from_u16(response.status().as_u16())?;
-        let mut builder = Response::builder().status(status);
+        let mut builder = Response::builder().status(response.status());
 
         for (name, value) in response.headers() {
             if name != "transfer-encoding" && name != "content-length" {
@@ -47,9 +62,41 @@ impl CodexProvider {
             }
         }
 
+        let stage = capture::response_stage(capture::UPSTREAM_RESPONSE, response.headers());
         let body = response.bytes().await?;
+        if let Some(capture) = &capture {
+            capture.write(&stage, &body);
+        }
         let response = builder.body(Body::from(body))?;
 
         Ok(response)
     }
+
+    /// List chat models available to the account
+    pub async fn list_models(client: &reqwest::Client, account: &Account, http: &HttpConfig) -> anyhow::Result<Vec<ModelInfo>> {
+        let response = client
+            .get(format!("{}/models", http.api_base(&Provider::Codex, API_BASE)))
+            .header("Authorization", format!("Bearer {}", account.credentials.access_token))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let body: Value = response.json().await?;
+        let models = body
+            .get("data")
+            .and_then(|d| d.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|m| m.get("id").and_then(|i| i.as_str()))
+                    .filter(|id| Provider::Codex.matches_model(id))
+                    .map(|id| ModelInfo {
+                        name: id.to_string(),
+                        ..Default::default()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(models)
+    }
 }
@@ -0,0 +1,263 @@
+use std::fmt;
+use std::time::Duration;
+
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde_json::{json, Value};
+
+use crate::accounts::Provider;
+
+/// Build an OpenAI error object: `{"error": {"message", "type", "param", "code"}}`
+pub fn openai_error(status: StatusCode, message: impl Into<String>, code: Option<&str>) -> Value {
+    json!({
+        "error": {
+            "message": message.into(),
+            "type": error_type(status),
+            "param": null,
+            "code": code,
+        }
+    })
+}
+
+/// OpenAI error `type` for a status code
+pub fn error_type(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        400 | 404 | 409 | 413 | 422 => "invalid_request_error",
+        401 => "authentication_error",
+        403 => "permission_error",
+        429 => "rate_limit_error",
+        _ => "server_error",
+    }
+}
+
+/// Error on the proxy path, classified so the router can pick a status code,
+/// decide whether another account or model might succeed, and label metrics.
+#[derive(Debug, thiserror::Error)]
+pub enum ProxyError {
+    /// The client's request is malformed or can't be served as asked
+    #[error("{0}")]
+    InvalidRequest(String),
+    /// The upstream response couldn't be translated to the OpenAI format
+    #[error("Failed to translate upstream response: {0}")]
+    Translation(String),
+    /// Upstream rejected the account's credentials (401/403)
+    #[error("{0}")]
+    Auth(UpstreamError),
+    /// Upstream rate limited the account (429)
+    #[error("{0}")]
+    RateLimited(UpstreamError),
+    /// Upstream rejected the request itself (other 4xx)
+    #[error("{0}")]
+    UpstreamClient(UpstreamError),
+    /// Upstream failed or is overloaded (5xx, Anthropic 529)
+    #[error("{0}")]
+    UpstreamServer(UpstreamError),
+    #[error("Upstream request timed out: {0}")]
+    Timeout(String),
+    /// Connection, DNS or TLS failure reaching the provider
+    #[error("Upstream connection failed: {0}")]
+    Connect(String),
+    /// No account or model could take the request
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+/// A failed upstream response
+#[derive(Debug)]
+pub struct UpstreamError {
+    pub provider: Provider,
+    pub status: u16,
+    pub retry_after: Option<Duration>,
+    /// The provider's error, normalized to an OpenAI error object
+    pub body: Value,
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.body["error"]["message"].as_str().unwrap_or_default();
+        write!(f, "{} returned HTTP {}: {}", self.provider, self.status, message)
+    }
+}
+
+impl ProxyError {
+    /// Classify a failed upstream response.
+    ///
+    /// The provider's message is lifted into `error.message` and its original
+    /// body is kept under `error.upstream` for debugging.
+    pub async fn from_upstream(provider: Provider, response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = response.bytes().await.unwrap_or_default();
+
+        let make = |client_status: StatusCode| UpstreamError {
+            provider,
+            status,
+            retry_after,
+            body: normalize_upstream_error(provider, client_status, status, &body),
+        };
+
+        match status {
+            401 | 403 => Self::Auth(make(StatusCode::BAD_GATEWAY)),
+            429 => Self::RateLimited(make(StatusCode::TOO_MANY_REQUESTS)),
+            400..=499 => {
+                let client_status = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_REQUEST);
+                Self::UpstreamClient(make(client_status))
+            }
+            // Anthropic's non-standard 529 (overloaded) becomes a 503
+            503 | 529 => Self::UpstreamServer(make(StatusCode::SERVICE_UNAVAILABLE)),
+            _ => Self::UpstreamServer(make(StatusCode::BAD_GATEWAY)),
+        }
+    }
+
+    /// Status code returned to the client
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Translation(_) | Self::Connect(_) | Self::Auth(_) => StatusCode::BAD_GATEWAY,
+            Self::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::UpstreamClient(e) => StatusCode::from_u16(e.status).unwrap_or(StatusCode::BAD_REQUEST),
+            Self::UpstreamServer(e) if matches!(e.status, 503 | 529) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::UpstreamServer(_) => StatusCode::BAD_GATEWAY,
+            Self::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Whether another account or fallback model might succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::Auth(_) | Self::RateLimited(_) | Self::UpstreamServer(_) | Self::Timeout(_) | Self::Connect(_) | Self::Unavailable(_)
+        )
+    }
+
+    /// Short, stable name of the error class, used as `error.code` and as a metrics label
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::Translation(_) => "translation_failed",
+            Self::Auth(_) => "upstream_auth_failed",
+            Self::RateLimited(_) => "rate_limited",
+            Self::UpstreamClient(_) => "upstream_client_error",
+            Self::UpstreamServer(_) => "upstream_server_error",
+            Self::Timeout(_) => "timeout",
+            Self::Connect(_) => "connect_failed",
+            Self::Unavailable(_) => "unavailable",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    /// The upstream response behind this error, if any
+    pub fn upstream(&self) -> Option<&UpstreamError> {
+        match self {
+            Self::Auth(e) | Self::RateLimited(e) | Self::UpstreamClient(e) | Self::UpstreamServer(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ProxyError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout(e.to_string())
+        } else if e.is_decode() {
+            Self::Translation(e.to_string())
+        } else if e.is_builder() {
+            Self::Internal(e.to_string())
+        } else {
+            Self::Connect(e.to_string())
+        }
+    }
+}
+
+impl From<axum::http::Error> for ProxyError {
+    fn from(e: axum::http::Error) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<axum::Error> for ProxyError {
+    fn from(e: axum::Error) -> Self {
+        Self::InvalidRequest(format!("Failed to read body: {}", e))
+    }
+}
+
+impl IntoResponse for ProxyError {
+    fn into_response(self) -> axum::response::Response {
+        let status = self.status();
+        let body = match self.upstream() {
+            Some(upstream) => upstream.body.clone(),
+            None => openai_error(status, self.to_string(), Some(self.label())),
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        if let Some(retry_after) = self.upstream().and_then(|e| e.retry_after) {
+            response.headers_mut().insert(header::RETRY_AFTER, retry_after.as_secs().into());
+        }
+        response
+    }
+}
+
+/// Normalize an upstream error body from any provider
+pub fn normalize_upstream_error(provider: Provider, status: StatusCode, upstream_status: u16, body: &[u8]) -> Value {
+    let original: Value = serde_json::from_slice(body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()));
+
+    let (message, code) = extract_message(&original);
+    let message = message.unwrap_or_else(|| format!("{} returned HTTP {}", provider, upstream_status));
+
+    let mut error = openai_error(status, message, code.as_deref());
+    error["error"]["upstream"] = json!({
+        "provider": provider.as_str(),
+        "status": upstream_status,
+        "body": original,
+    });
+    error
+}
+
+/// Normalize an error event received mid-stream
+pub fn normalize_stream_error(provider: Provider, event: &Value) -> Value {
+    let (message, code) = extract_message(event);
+    let message = message.unwrap_or_else(|| format!("{} stream error", provider));
+
+    let mut error = openai_error(StatusCode::BAD_GATEWAY, message, code.as_deref());
+    error["error"]["upstream"] = json!({
+        "provider": provider.as_str(),
+        "body": event,
+    });
+    error
+}
+
+/// Message and code from the error shapes used by each provider:
+///
+/// - OpenAI: `{"error": {"message", "type", "code"}}`
+/// - Anthropic: `{"type": "error", "error": {"type", "message"}}`
+/// - Gemini: `{"error": {"code", "message", "status"}}`
+fn extract_message(body: &Value) -> (Option<String>, Option<String>) {
+    let error = body.get("error").unwrap_or(body);
+
+    let message = error
+        .get("message")
+        .and_then(|m| m.as_str())
+        .or_else(|| body.as_str())
+        .filter(|m| !m.is_empty())
+        .map(String::from);
+
+    let code = error
+        .get("code")
+        .and_then(|c| c.as_str())
+        .or_else(|| error.get("status").and_then(|s| s.as_str()))
+        .or_else(|| error.get("type").and_then(|t| t.as_str()))
+        .map(String::from);
+
+    (message, code)
+}
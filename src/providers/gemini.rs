@@ -1,17 +1,42 @@
 use axum::body::Body;
-use axum::http::{Request, Response, StatusCode};
+use axum::http::{Request, Response};
 use http_body_util::BodyExt;
 use serde_json::Value;
 
-use crate::accounts::Account;
+use super::structured::{self, ResponseFormat};
+use super::messages::{self, Conversation, Part, PartKind, Role};
+use super::{params, reasoning, stream, ModelInfo, ProxyError};
+use crate::accounts::{Account, Provider};
+use crate::capture::{self, Capture};
+use crate::config::{Config, HttpConfig};
 
 const API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
 
+/// User turn appended after a trailing model turn, which Gemini can't prefill
+const CONTINUE_PREFILL_TEXT: &str = "Continue exactly where your previous message left off.";
+
 pub struct GeminiProvider;
 
 impl GeminiProvider {
-    pub async fn proxy(account: &Account, request: Request<Body>) -> anyhow::Result<Response<Body>> {
-        let client = reqwest::Client::new();
+    /// OpenAI parameters translated by `convert_request`
+    pub const SUPPORTED_PARAMS: &'static [&'static str] = &[
+        "max_tokens",
+        "max_completion_tokens",
+        "temperature",
+        "top_p",
+        "stop",
+        "presence_penalty",
+        "frequency_penalty",
+        "seed",
+        "n",
+        "logprobs",
+        "top_logprobs",
+        "reasoning_effort",
+        "response_format",
+    ];
+
+    pub async fn proxy(client: &reqwest::Client, account: &Account, request: Request<Body>, config: &Config) -> Result<Response<Body>, ProxyError> {
+        let api_base = config.http.api_base(&Provider::Gemini, API_BASE);
 
         // Get request body
         let (parts, body) = request.into_parts();
@@ -19,34 +44,53 @@ impl GeminiProvider {
         let body_bytes = body.collect().await?.to_bytes();
 
         // Parse the OpenAI request to get the model
-        let body_json: Value = serde_json::from_slice(&body_bytes)?;
+        let body_json: Value = serde_json::from_slice(&body_bytes)
+            .map_err(|e| ProxyError::InvalidRequest(format!("Invalid JSON: {}", e)))?;
         let model = body_json
             .get("model")
             .and_then(|m| m.as_str())
             .unwrap_or("gemini-2.0-flash")
             .to_string();
 
-        // Convert model name if needed
-        let gemini_model = Self::map_model(&model);
+        let is_chat = path == "/v1/chat/completions" || path == "/chat/completions" || path == "chat/completions";
+        let streaming = body_json.get("stream").and_then(|s| s.as_bool()).unwrap_or(false);
+        let include_usage = body_json
+            .pointer("/stream_options/include_usage")
+            .and_then(|u| u.as_bool())
+            .unwrap_or(false);
 
         // For Gemini, convert OpenAI format to Gemini format
-        let (url, body_bytes) = if path == "/v1/chat/completions" || path == "/chat/completions" || path == "chat/completions" {
+        let (url, body_bytes) = if is_chat {
             let converted = Self::convert_request(body_json)?;
-            let url = format!("{}/models/{}:generateContent", API_BASE, gemini_model);
-            (url, serde_json::to_vec(&converted)?)
+            let url = if streaming {
+                format!("{}/models/{}:streamGenerateContent?alt=sse", api_base, model)
+            } else {
+                format!("{}/models/{}:generateContent", api_base, model)
+            };
+            (url, serde_json::to_vec(&converted).map_err(|e| ProxyError::Internal(e.to_string()))?)
         } else {
-            (format!("{}{}", API_BASE, path), body_bytes.to_vec())
+            (format!("{}{}", api_base, path), body_bytes.to_vec())
         };
 
+        let capture = parts.extensions.get::<Capture>().cloned();
+        if let Some(capture) = &capture {
+            capture.write_request(capture::UPSTREAM_REQUEST, parts.method.as_str(), &url, None, &body_bytes);
+        }
+
         // Build proxied request
         let mut req_builder = client
             .request(parts.method.clone(), &url)
             .header("Authorization", format!("Bearer {}", account.credentials.access_token))
             .header("Content-Type", "application/json");
 
-        // Copy relevant headers
+        // Copy relevant headers, keeping the account's own User-Agent if it has one
+        let keep_user_agent = account.egress.user_agent.is_some();
         for (name, value) in parts.headers.iter() {
-            if name != "host" && name != "authorization" && name != "content-length" {
+            let skip = name == "host"
+                || name == "authorization"
+                || name == "content-length"
+                || (keep_user_agent && name == "user-agent");
+            if !skip {
                 req_builder = req_builder.header(name.clone(), value.clone());
             }
         }
@@ -56,9 +100,12 @@ impl GeminiProvider {
             .send()
             .await?;
 
+        if !response.status().is_success() {
+            return Err(ProxyError::from_upstream(Provider::Gemini, response).await);
+        }
+
         // Build response
-        let status = StatusCode::from_u16(response.status().as_u16())?;
-        let mut builder = Response::builder().status(status);
+        let mut builder = Response::builder().status(response.status());
 
         for (name, value) in response.headers() {
             if name != "transfer-encoding" && name != "content-length" {
@@ -66,12 +113,22 @@ impl GeminiProvider {
             }
         }
 
+        // Translate Gemini stream responses into OpenAI chunks as they arrive
+        if is_chat && streaming {
+            let body = stream::translate_sse(response, Self::stream_translator(model, include_usage), capture);
+            return Ok(builder.body(body)?);
+        }
+
+        let stage = capture::response_stage(capture::UPSTREAM_RESPONSE, response.headers());
         let body = response.bytes().await?;
+        if let Some(capture) = &capture {
+            capture.write(&stage, &body);
+        }
 
         // Convert Gemini response to OpenAI format
-        let converted_body = if path == "/v1/chat/completions" || path == "/chat/completions" || path == "chat/completions" {
+        let converted_body = if is_chat {
             if let Ok(gemini_response) = serde_json::from_slice::<Value>(&body) {
-                serde_json::to_vec(&Self::convert_response(gemini_response, &model)?)?
+                serde_json::to_vec(&Self::convert_response(gemini_response, &model)?).map_err(|e| ProxyError::Translation(e.to_string()))?
             } else {
                 body.to_vec()
             }
@@ -84,47 +141,78 @@ impl GeminiProvider {
         Ok(response)
     }
 
-    /// Map OpenAI-style model names to Gemini model names
-    fn map_model(model: &str) -> &str {
-        let model_lower = model.to_lowercase();
-        if model_lower.contains("gemini") {
-            model
-        } else if model_lower.contains("flash") {
-            "gemini-2.0-flash"
-        } else if model_lower.contains("pro") {
-            "gemini-1.5-pro"
-        } else {
-            "gemini-2.0-flash"
-        }
+    /// List models that support `generateContent`, with their token limits
+    pub async fn list_models(client: &reqwest::Client, account: &Account, http: &HttpConfig) -> anyhow::Result<Vec<ModelInfo>> {
+        let response = client
+            .get(format!("{}/models", http.api_base(&Provider::Gemini, API_BASE)))
+            .header("Authorization", format!("Bearer {}", account.credentials.access_token))
+            .query(&[("pageSize", "1000")])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let body: Value = response.json().await?;
+        let models = body
+            .get("models")
+            .and_then(|m| m.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter(|m| {
+                        m.get("supportedGenerationMethods")
+                            .and_then(|s| s.as_array())
+                            .is_some_and(|s| s.iter().any(|v| v == "generateContent"))
+                    })
+                    .filter_map(|m| {
+                        let name = m.get("name").and_then(|n| n.as_str())?;
+                        let limit = |key: &str| m.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+                        Some(ModelInfo {
+                            name: name.trim_start_matches("models/").to_string(),
+                            context_length: limit("inputTokenLimit"),
+                            max_output_tokens: limit("outputTokenLimit"),
+                            ..Default::default()
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(models)
     }
 
     /// Convert OpenAI chat completion request to Gemini format
-    fn convert_request(openai_req: Value) -> anyhow::Result<Value> {
-        let mut contents = Vec::new();
-        let mut system_instruction = None;
-
-        if let Some(messages) = openai_req.get("messages").and_then(|m| m.as_array()) {
-            for msg in messages {
-                let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or("user");
-                let content = msg.get("content").and_then(|c| c.as_str()).unwrap_or("");
-
-                if role == "system" {
-                    system_instruction = Some(serde_json::json!({
-                        "parts": [{"text": content}]
-                    }));
-                } else {
-                    let gemini_role = match role {
-                        "assistant" => "model",
-                        _ => "user",
-                    };
-                    contents.push(serde_json::json!({
-                        "role": gemini_role,
-                        "parts": [{"text": content}]
-                    }));
-                }
-            }
+    #[tracing::instrument(name = "translate_request", skip_all)]
+    fn convert_request(openai_req: Value) -> Result<Value, ProxyError> {
+        let conversation = Conversation::from_request(&openai_req);
+        let mut contents: Vec<Value> = conversation
+            .turns
+            .iter()
+            .map(|turn| {
+                let role = match turn.role {
+                    Role::User => "user",
+                    Role::Assistant => "model",
+                };
+                let parts = turn.parts.iter().map(Self::convert_part).collect::<Result<Vec<_>, _>>()?;
+                Ok(serde_json::json!({
+                    "role": role,
+                    "parts": parts,
+                }))
+            })
+            .collect::<Result<_, ProxyError>>()?;
+
+        // Gemini has no assistant prefill: ask it to continue a trailing model turn
+        if conversation.turns.last().is_some_and(|t| t.role == Role::Assistant) {
+            contents.push(serde_json::json!({
+                "role": "user",
+                "parts": [{ "text": CONTINUE_PREFILL_TEXT }],
+            }));
         }
 
+        let system_instruction = conversation.system_prompt().map(|system| {
+            serde_json::json!({
+                "parts": [{"text": system}]
+            })
+        });
+
         let mut gemini_req = serde_json::json!({
             "contents": contents,
         });
@@ -136,7 +224,7 @@ impl GeminiProvider {
         // Generation config
         let mut generation_config = serde_json::json!({});
 
-        if let Some(max_tokens) = openai_req.get("max_tokens") {
+        if let Some(max_tokens) = params::max_output_tokens(&openai_req) {
             generation_config["maxOutputTokens"] = max_tokens.clone();
         }
 
@@ -144,6 +232,41 @@ impl GeminiProvider {
             generation_config["temperature"] = temp.clone();
         }
 
+        // Sampling parameters with a direct Gemini equivalent
+        for (openai, gemini) in [
+            ("top_p", "topP"),
+            ("presence_penalty", "presencePenalty"),
+            ("frequency_penalty", "frequencyPenalty"),
+            ("seed", "seed"),
+            ("n", "candidateCount"),
+            ("logprobs", "responseLogprobs"),
+            ("top_logprobs", "logprobs"),
+        ] {
+            if let Some(value) = openai_req.get(openai).filter(|v| !v.is_null()) {
+                generation_config[gemini] = value.clone();
+            }
+        }
+
+        if let Some(stop) = params::stop_sequences(&openai_req) {
+            generation_config["stopSequences"] = stop;
+        }
+
+        if let Some(format) = ResponseFormat::from_request(&openai_req)? {
+            generation_config["responseMimeType"] = Value::from("application/json");
+            if let ResponseFormat::JsonSchema { schema, .. } = &format {
+                generation_config["responseSchema"] = structured::to_gemini_schema(schema)?;
+            }
+        }
+
+        if let Some(effort) = reasoning::requested_effort(&openai_req) {
+            let budget = reasoning::thinking_budget(effort)
+                .ok_or_else(|| ProxyError::InvalidRequest(format!("Unsupported reasoning_effort: {}", effort)))?;
+            generation_config["thinkingConfig"] = serde_json::json!({
+                "thinkingBudget": budget,
+                "includeThoughts": true,
+            });
+        }
+
         if generation_config.as_object().map(|o| !o.is_empty()).unwrap_or(false) {
             gemini_req["generationConfig"] = generation_config;
         }
@@ -151,19 +274,49 @@ impl GeminiProvider {
         Ok(gemini_req)
     }
 
-    /// Convert Gemini response to OpenAI format
-    fn convert_response(gemini_resp: Value, model: &str) -> anyhow::Result<Value> {
-        let content = gemini_resp
-            .get("candidates")
-            .and_then(|c| c.as_array())
-            .and_then(|arr| arr.first())
-            .and_then(|candidate| candidate.get("content"))
-            .and_then(|content| content.get("parts"))
-            .and_then(|parts| parts.as_array())
-            .and_then(|parts| parts.first())
-            .and_then(|part| part.get("text"))
-            .and_then(|t| t.as_str())
-            .unwrap_or("");
+    /// Convert a message part to a Gemini part.
+    ///
+    /// Gemini's `fileData` only takes files it hosts, so images must be sent
+    /// inline as `data:` URLs.
+    fn convert_part(part: &Part) -> Result<Value, ProxyError> {
+        match &part.kind {
+            PartKind::Text(text) => Ok(serde_json::json!({ "text": text })),
+            PartKind::Image { url } => {
+                let (mime_type, data) = messages::parse_data_url(url).ok_or_else(|| {
+                    ProxyError::InvalidRequest("Gemini only accepts images as base64 data: URLs".to_string())
+                })?;
+                Ok(serde_json::json!({
+                    "inlineData": { "mimeType": mime_type, "data": data },
+                }))
+            }
+        }
+    }
+
+    /// Convert Gemini response to OpenAI format, one choice per candidate
+    #[tracing::instrument(name = "translate_response", skip_all)]
+    fn convert_response(gemini_resp: Value, model: &str) -> Result<Value, ProxyError> {
+        let choices: Vec<Value> = Self::candidates(&gemini_resp)
+            .iter()
+            .enumerate()
+            .map(|(i, candidate)| {
+                let (content, thoughts) = Self::split_parts(candidate);
+
+                let mut message = serde_json::json!({
+                    "role": "assistant",
+                    "content": content,
+                });
+                if !thoughts.is_empty() {
+                    message["reasoning_content"] = Value::String(thoughts);
+                }
+
+                serde_json::json!({
+                    "index": Self::candidate_index(candidate, i),
+                    "message": message,
+                    "logprobs": Self::convert_logprobs(candidate),
+                    "finish_reason": Self::finish_reason(Some(candidate)).unwrap_or("stop"),
+                })
+            })
+            .collect();
 
         let usage = gemini_resp.get("usageMetadata");
         let prompt_tokens = usage.and_then(|u| u.get("promptTokenCount")).and_then(|t| t.as_i64()).unwrap_or(0);
@@ -174,14 +327,7 @@ impl GeminiProvider {
             "object": "chat.completion",
             "created": chrono::Utc::now().timestamp(),
             "model": model,
-            "choices": [{
-                "index": 0,
-                "message": {
-                    "role": "assistant",
-                    "content": content,
-                },
-                "finish_reason": "stop",
-            }],
+            "choices": choices,
             "usage": {
                 "prompt_tokens": prompt_tokens,
                 "completion_tokens": completion_tokens,
@@ -191,4 +337,183 @@ impl GeminiProvider {
 
         Ok(openai_response)
     }
+
+    /// Build a translator from Gemini stream responses to OpenAI chunks
+    fn stream_translator(model: String, include_usage: bool) -> impl FnMut(Value) -> Vec<Value> + Send + 'static {
+        let id = format!("chatcmpl-{}", uuid::Uuid::new_v4());
+        let created = chrono::Utc::now().timestamp();
+        let mut started = std::collections::HashSet::new();
+
+        move |event| {
+            let mut chunks = Vec::new();
+
+            for (i, candidate) in Self::candidates(&event).iter().enumerate() {
+                let index = Self::candidate_index(candidate, i);
+                let (content, thoughts) = Self::split_parts(candidate);
+
+                let mut delta = serde_json::json!({});
+                if started.insert(index) {
+                    delta["role"] = Value::from("assistant");
+                }
+                if !content.is_empty() {
+                    delta["content"] = Value::String(content);
+                }
+                if !thoughts.is_empty() {
+                    delta["reasoning_content"] = Value::String(thoughts);
+                }
+
+                let mut chunk = stream::chunk(&id, &model, created, delta, Self::finish_reason(Some(candidate)));
+                chunk["choices"][0]["index"] = Value::from(index);
+                if let Some(logprobs) = Self::convert_logprobs(candidate) {
+                    chunk["choices"][0]["logprobs"] = logprobs;
+                }
+                chunks.push(chunk);
+            }
+
+            // Usage arrives with the final response
+            let finished = chunks.iter().any(|c| !c["choices"][0]["finish_reason"].is_null());
+            if include_usage && finished {
+                if let Some(usage) = event.get("usageMetadata") {
+                    let prompt_tokens = usage.get("promptTokenCount").and_then(|t| t.as_i64()).unwrap_or(0);
+                    let completion_tokens = usage.get("candidatesTokenCount").and_then(|t| t.as_i64()).unwrap_or(0);
+                    if let Some(last) = chunks.last_mut() {
+                        last["usage"] = serde_json::json!({
+                            "prompt_tokens": prompt_tokens,
+                            "completion_tokens": completion_tokens,
+                            "total_tokens": prompt_tokens + completion_tokens,
+                        });
+                    }
+                }
+            }
+
+            chunks
+        }
+    }
+
+    fn candidates(response: &Value) -> &[Value] {
+        response
+            .get("candidates")
+            .and_then(|c| c.as_array())
+            .map(|arr| arr.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn candidate_index(candidate: &Value, position: usize) -> u64 {
+        candidate.get("index").and_then(|i| i.as_u64()).unwrap_or(position as u64)
+    }
+
+    /// Convert a candidate's `logprobsResult` to OpenAI `logprobs`
+    fn convert_logprobs(candidate: &Value) -> Option<Value> {
+        let result = candidate.get("logprobsResult")?;
+        let chosen = result.get("chosenCandidates")?.as_array()?;
+        let top = result.get("topCandidates").and_then(|t| t.as_array());
+
+        let token = |c: &Value| {
+            serde_json::json!({
+                "token": c.get("token").and_then(|t| t.as_str()).unwrap_or_default(),
+                "logprob": c.get("logProbability").and_then(|l| l.as_f64()).unwrap_or_default(),
+                "bytes": null,
+            })
+        };
+
+        let content: Vec<Value> = chosen
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let mut entry = token(c);
+                entry["top_logprobs"] = top
+                    .and_then(|t| t.get(i))
+                    .and_then(|t| t.get("candidates"))
+                    .and_then(|c| c.as_array())
+                    .map(|alts| Value::Array(alts.iter().map(token).collect()))
+                    .unwrap_or_else(|| Value::Array(vec![]));
+                entry
+            })
+            .collect();
+
+        Some(serde_json::json!({ "content": content }))
+    }
+
+    /// Split a candidate's parts into answer text and thought summaries
+    fn split_parts(candidate: &Value) -> (String, String) {
+        let mut content = String::new();
+        let mut thoughts = String::new();
+
+        let parts = candidate
+            .pointer("/content/parts")
+            .and_then(|p| p.as_array())
+            .map(|p| p.as_slice())
+            .unwrap_or_default();
+
+        for part in parts {
+            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                if part.get("thought").and_then(|t| t.as_bool()).unwrap_or(false) {
+                    thoughts.push_str(text);
+                } else {
+                    content.push_str(text);
+                }
+            }
+        }
+
+        (content, thoughts)
+    }
+
+    /// Map a Gemini finish reason to an OpenAI finish reason
+    fn finish_reason(candidate: Option<&Value>) -> Option<&'static str> {
+        match candidate?.get("finishReason")?.as_str()? {
+            "MAX_TOKENS" => Some("length"),
+            "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => Some("content_filter"),
+            _ => Some("stop"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(content: Value) -> Value {
+        serde_json::json!({
+            "model": "gemini-2.5-pro",
+            "messages": [{ "role": "user", "content": content }],
+        })
+    }
+
+    #[test]
+    fn inlines_data_url_images() {
+        let converted = GeminiProvider::convert_request(request(serde_json::json!([
+            { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } },
+        ])))
+        .unwrap();
+
+        assert_eq!(
+            converted["contents"][0]["parts"][0]["inlineData"],
+            serde_json::json!({ "mimeType": "image/png", "data": "iVBORw0KGgo=" })
+        );
+    }
+
+    #[test]
+    fn rejects_remote_images() {
+        let result = GeminiProvider::convert_request(request(serde_json::json!([
+            { "type": "image_url", "image_url": { "url": "https://example.com/cat.png" } },
+        ])));
+
+        assert!(matches!(result, Err(ProxyError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn asks_to_continue_a_trailing_model_turn() {
+        let converted = GeminiProvider::convert_request(serde_json::json!({
+            "messages": [
+                { "role": "user", "content": "Write a haiku" },
+                { "role": "assistant", "content": "Autumn" },
+            ],
+        }))
+        .unwrap();
+
+        let contents = converted["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 3);
+        assert_eq!(contents[1]["role"], "model");
+        assert_eq!(contents[2]["parts"][0]["text"], CONTINUE_PREFILL_TEXT);
+    }
 }
@@ -0,0 +1,280 @@
+use serde_json::Value;
+
+/// Provider-neutral view of OpenAI `messages`, ready to render as Anthropic
+/// `messages` or Gemini `contents`.
+///
+/// - `system` and `developer` messages are collected, in order, into `system`
+/// - `tool` and `function` results become user turns, and assistant
+///   `tool_calls` become text, since tool definitions aren't translated
+/// - empty turns are dropped and adjacent turns with the same role are merged,
+///   so roles strictly alternate
+#[derive(Debug, Default)]
+pub struct Conversation {
+    /// Text parts of all system messages
+    pub system: Vec<Part>,
+    pub turns: Vec<Turn>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug)]
+pub struct Turn {
+    pub role: Role,
+    pub parts: Vec<Part>,
+}
+
+/// A content part, with any Anthropic `cache_control` the client set on it
+#[derive(Debug)]
+pub struct Part {
+    pub kind: PartKind,
+    pub cache_control: Option<Value>,
+}
+
+#[derive(Debug)]
+pub enum PartKind {
+    Text(String),
+    /// `image_url` content part; `url` may be a `data:` URL
+    Image { url: String },
+}
+
+impl Part {
+    fn text(text: String) -> Self {
+        Self {
+            kind: PartKind::Text(text),
+            cache_control: None,
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        matches!(&self.kind, PartKind::Text(t) if t.trim().is_empty())
+    }
+}
+
+impl Conversation {
+    pub fn from_request(request: &Value) -> Self {
+        let mut conversation = Self::default();
+
+        let messages = request
+            .get("messages")
+            .and_then(|m| m.as_array())
+            .map(|m| m.as_slice())
+            .unwrap_or_default();
+
+        for msg in messages {
+            let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or("user");
+            let content = msg.get("content").unwrap_or(&Value::Null);
+
+            match role {
+                "system" | "developer" => {
+                    conversation.system.extend(
+                        parts_of(content)
+                            .into_iter()
+                            .filter(|p| matches!(p.kind, PartKind::Text(_)) && !p.is_blank()),
+                    );
+                }
+                "assistant" => {
+                    let mut parts = parts_of(content);
+                    parts.extend(tool_calls_of(msg).into_iter().map(Part::text));
+                    conversation.push(Role::Assistant, parts);
+                }
+                "tool" | "function" => {
+                    let name = msg
+                        .get("name")
+                        .or_else(|| msg.get("tool_call_id"))
+                        .and_then(|n| n.as_str())
+                        .unwrap_or("tool");
+                    let text = format!("[Result of {}]\n{}", name, text_of(content));
+                    conversation.push(Role::User, vec![Part::text(text)]);
+                }
+                "user" => conversation.push(Role::User, parts_of(content)),
+                other => {
+                    tracing::warn!("Treating message with unknown role '{}' as user", other);
+                    conversation.push(Role::User, parts_of(content));
+                }
+            }
+        }
+
+        conversation
+    }
+
+    /// System messages joined into one prompt
+    pub fn system_prompt(&self) -> Option<String> {
+        let texts: Vec<&str> = self
+            .system
+            .iter()
+            .filter_map(|p| match &p.kind {
+                PartKind::Text(text) => Some(text.as_str()),
+                PartKind::Image { .. } => None,
+            })
+            .collect();
+
+        (!texts.is_empty()).then(|| texts.join("\n\n"))
+    }
+
+    fn push(&mut self, role: Role, parts: Vec<Part>) {
+        let parts: Vec<Part> = parts.into_iter().filter(|p| !p.is_blank()).collect();
+        if parts.is_empty() {
+            return;
+        }
+
+        match self.turns.last_mut() {
+            Some(last) if last.role == role => last.parts.extend(parts),
+            _ => self.turns.push(Turn { role, parts }),
+        }
+    }
+}
+
+/// Content as parts: a plain string, or an array of `text`/`image_url` parts
+fn parts_of(content: &Value) -> Vec<Part> {
+    match content {
+        Value::String(s) => vec![Part::text(s.clone())],
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| {
+                let kind = match item.get("type").and_then(|t| t.as_str()) {
+                    Some("text") => PartKind::Text(item.get("text").and_then(|t| t.as_str())?.to_string()),
+                    Some("image_url") => PartKind::Image {
+                        url: item
+                            .pointer("/image_url/url")
+                            .or_else(|| item.get("image_url"))
+                            .and_then(|u| u.as_str())?
+                            .to_string(),
+                    },
+                    other => {
+                        tracing::warn!("Dropping unsupported content part type: {:?}", other);
+                        return None;
+                    }
+                };
+
+                Some(Part {
+                    kind,
+                    cache_control: item.get("cache_control").cloned(),
+                })
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Text of a content value, ignoring non-text parts
+fn text_of(content: &Value) -> String {
+    parts_of(content)
+        .into_iter()
+        .filter_map(|p| match p.kind {
+            PartKind::Text(t) => Some(t),
+            PartKind::Image { .. } => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Assistant `tool_calls` (and legacy `function_call`) rendered as text
+fn tool_calls_of(msg: &Value) -> Vec<String> {
+    let calls = msg
+        .get("tool_calls")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.get("function"))
+        .chain(msg.get("function_call"));
+
+    calls
+        .map(|f| {
+            let name = f.get("name").and_then(|n| n.as_str()).unwrap_or("tool");
+            let arguments = f.get("arguments").and_then(|a| a.as_str()).unwrap_or("{}");
+            format!("[Called {} with {}]", name, arguments)
+        })
+        .collect()
+}
+
+/// Split a `data:` URL into media type and base64 payload
+pub fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("data:")?;
+    let (meta, data) = rest.split_once(',')?;
+    let media_type = meta.strip_suffix(";base64")?;
+    Some((media_type, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation(messages: Value) -> Conversation {
+        Conversation::from_request(&serde_json::json!({ "messages": messages }))
+    }
+
+    fn texts(turn: &Turn) -> Vec<&str> {
+        turn.parts
+            .iter()
+            .map(|p| match &p.kind {
+                PartKind::Text(text) => text.as_str(),
+                PartKind::Image { url } => url.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collects_system_and_developer_messages() {
+        let conversation = conversation(serde_json::json!([
+            { "role": "system", "content": "Be brief" },
+            { "role": "user", "content": "Hi" },
+            { "role": "developer", "content": [{ "type": "text", "text": "Use French" }, { "type": "text", "text": " " }] },
+        ]));
+
+        assert_eq!(conversation.system_prompt().as_deref(), Some("Be brief\n\nUse French"));
+        assert_eq!(conversation.turns.len(), 1);
+    }
+
+    #[test]
+    fn merges_adjacent_turns_and_drops_empty_ones() {
+        let conversation = conversation(serde_json::json!([
+            { "role": "user", "content": "One" },
+            { "role": "assistant", "content": "" },
+            { "role": "user", "content": [
+                { "type": "text", "text": "Two" },
+                { "type": "image_url", "image_url": { "url": "data:image/png;base64,AA==" } },
+            ] },
+            { "role": "assistant", "content": null },
+        ]));
+
+        assert_eq!(conversation.turns.len(), 1);
+        assert_eq!(texts(&conversation.turns[0]), ["One", "Two", "data:image/png;base64,AA=="]);
+    }
+
+    #[test]
+    fn renders_tool_calls_and_results_as_text() {
+        let conversation = conversation(serde_json::json!([
+            { "role": "user", "content": "Weather?" },
+            { "role": "assistant", "content": null, "tool_calls": [
+                { "id": "call_1", "type": "function", "function": { "name": "weather", "arguments": "{\"city\":\"Oslo\"}" } },
+            ] },
+            { "role": "tool", "tool_call_id": "call_1", "content": "Rain" },
+            { "role": "user", "content": "Thanks" },
+        ]));
+
+        let roles: Vec<Role> = conversation.turns.iter().map(|t| t.role).collect();
+        assert_eq!(roles, [Role::User, Role::Assistant, Role::User]);
+        assert_eq!(texts(&conversation.turns[1]), ["[Called weather with {\"city\":\"Oslo\"}]"]);
+        assert_eq!(texts(&conversation.turns[2]), ["[Result of call_1]\nRain", "Thanks"]);
+    }
+
+    #[test]
+    fn keeps_cache_control_on_parts() {
+        let conversation = conversation(serde_json::json!([
+            { "role": "user", "content": [{ "type": "text", "text": "Doc", "cache_control": { "type": "ephemeral" } }] },
+        ]));
+
+        assert_eq!(conversation.turns[0].parts[0].cache_control, Some(serde_json::json!({ "type": "ephemeral" })));
+    }
+
+    #[test]
+    fn parses_base64_data_urls_only() {
+        assert_eq!(parse_data_url("data:image/jpeg;base64,/9j/"), Some(("image/jpeg", "/9j/")));
+        assert_eq!(parse_data_url("data:text/plain,hello"), None);
+        assert_eq!(parse_data_url("https://example.com/a.png"), None);
+    }
+}
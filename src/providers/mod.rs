@@ -1,26 +1,41 @@
 mod codex;
 mod claude;
+mod errors;
 mod gemini;
+mod messages;
+mod params;
+mod reasoning;
 mod registry;
+mod routing;
+mod stream;
+mod structured;
 
 use axum::body::Body;
 use axum::http::{Request, Response};
 
 use crate::accounts::{Account, Provider};
+use crate::config::Config;
 
 pub use codex::CodexProvider;
 pub use claude::ClaudeProvider;
 pub use gemini::GeminiProvider;
-pub use registry::ModelRegistry;
+pub use errors::{openai_error, ProxyError};
+pub use params::check_supported;
+pub use reasoning::split_effort_suffix;
+pub use registry::{ModelInfo, ModelRegistry};
+pub use routing::{configured_models, resolve_model};
+pub use stream::{is_event_stream, rewrite_model};
 
 /// Proxy a request to the appropriate provider
 pub async fn proxy_request(
+    client: &reqwest::Client,
     account: &Account,
     request: Request<Body>,
-) -> anyhow::Result<Response<Body>> {
+    config: &Config,
+) -> Result<Response<Body>, ProxyError> {
     match account.provider {
-        Provider::Codex => CodexProvider::proxy(account, request).await,
-        Provider::Claude => ClaudeProvider::proxy(account, request).await,
-        Provider::Gemini => GeminiProvider::proxy(account, request).await,
+        Provider::Codex => CodexProvider::proxy(client, account, request, config).await,
+        Provider::Claude => ClaudeProvider::proxy(client, account, request, config).await,
+        Provider::Gemini => GeminiProvider::proxy(client, account, request, config).await,
     }
 }
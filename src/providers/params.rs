@@ -0,0 +1,156 @@
+use serde_json::Value;
+
+use super::messages::{Conversation, Role};
+use super::{ClaudeProvider, GeminiProvider, ProxyError};
+use crate::accounts::Provider;
+
+/// OpenAI request fields that are not generation parameters
+const ALWAYS_ACCEPTED: &[&str] = &["model", "messages", "stream", "stream_options"];
+
+/// Check a chat completion request for OpenAI parameters the provider's
+/// converter can't translate.
+///
+/// In strict mode any such parameter is an error, as is a conversation the
+/// converter could only send by adding a user turn of its own; otherwise
+/// parameters are logged and dropped by the converter. Parameters set to their
+/// no-op value (`n: 1`, `presence_penalty: 0`, `logprobs: false`, `null`) are
+/// always accepted.
+pub fn check_supported(provider: &Provider, request: &Value, strict: bool) -> Result<(), ProxyError> {
+    let supported = match provider {
+        // Sent to OpenAI unchanged
+        Provider::Codex => return Ok(()),
+        Provider::Claude => ClaudeProvider::SUPPORTED_PARAMS,
+        Provider::Gemini => GeminiProvider::SUPPORTED_PARAMS,
+    };
+
+    let unsupported: Vec<&str> = request
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, value)| {
+            !ALWAYS_ACCEPTED.contains(&key.as_str()) && !supported.contains(&key.as_str()) && !is_noop(key, value)
+        })
+        .map(|(key, _)| key.as_str())
+        .collect();
+
+    if strict {
+        if let Some(reason) = synthetic_turn(provider, request) {
+            return Err(ProxyError::InvalidRequest(reason.to_string()));
+        }
+    }
+
+    if unsupported.is_empty() {
+        return Ok(());
+    }
+
+    if strict {
+        return Err(ProxyError::InvalidRequest(format!(
+            "Unsupported parameter(s) for {}: {}",
+            provider,
+            unsupported.join(", ")
+        )));
+    }
+
+    tracing::warn!("Dropping parameter(s) unsupported by {}: {}", provider, unsupported.join(", "));
+    Ok(())
+}
+
+/// Why the converter would insert a user turn the client didn't send, if it would
+fn synthetic_turn(provider: &Provider, request: &Value) -> Option<&'static str> {
+    let conversation = Conversation::from_request(request);
+    match provider {
+        Provider::Claude if conversation.turns.first().is_some_and(|t| t.role == Role::Assistant) => {
+            Some("Claude requires the conversation to start with a user message")
+        }
+        Provider::Gemini if conversation.turns.last().is_some_and(|t| t.role == Role::Assistant) => {
+            Some("Gemini doesn't support a trailing assistant message (prefill)")
+        }
+        _ => None,
+    }
+}
+
+/// `stop` as a list of sequences (OpenAI accepts a string or an array)
+pub fn stop_sequences(request: &Value) -> Option<Value> {
+    match request.get("stop")? {
+        Value::String(s) => Some(serde_json::json!([s])),
+        Value::Array(items) if !items.is_empty() => Some(Value::Array(items.clone())),
+        _ => None,
+    }
+}
+
+/// Output token limit, preferring `max_completion_tokens` over the legacy `max_tokens`
+pub fn max_output_tokens(request: &Value) -> Option<&Value> {
+    request
+        .get("max_completion_tokens")
+        .filter(|v| !v.is_null())
+        .or_else(|| request.get("max_tokens").filter(|v| !v.is_null()))
+}
+
+fn is_noop(key: &str, value: &Value) -> bool {
+    value.is_null()
+        || match key {
+            "n" => value.as_u64() == Some(1),
+            "presence_penalty" | "frequency_penalty" => value.as_f64() == Some(0.0),
+            "logprobs" => value.as_bool() == Some(false),
+            _ => false,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(messages: Value) -> Value {
+        serde_json::json!({ "model": "m", "messages": messages })
+    }
+
+    #[test]
+    fn accepts_noop_and_drops_unsupported_when_lenient() {
+        let req = serde_json::json!({ "model": "m", "messages": [], "n": 1, "logprobs": false, "tools": [] });
+
+        assert!(check_supported(&Provider::Claude, &req, false).is_ok());
+        assert!(check_supported(&Provider::Codex, &req, true).is_ok());
+    }
+
+    #[test]
+    fn strict_rejects_unsupported_parameters() {
+        let req = serde_json::json!({ "model": "m", "messages": [], "seed": 7, "temperature": 0.5 });
+
+        assert!(check_supported(&Provider::Gemini, &req, true).is_ok());
+        let message = check_supported(&Provider::Claude, &req, true).unwrap_err().to_string();
+        assert!(message.contains("seed"));
+        assert!(!message.contains("temperature"));
+    }
+
+    #[test]
+    fn strict_rejects_conversations_needing_a_synthetic_turn() {
+        let leading = request(serde_json::json!([
+            { "role": "system", "content": "Be brief" },
+            { "role": "assistant", "content": "Hi" },
+            { "role": "user", "content": "Hello" },
+        ]));
+        let trailing = request(serde_json::json!([
+            { "role": "user", "content": "Hello" },
+            { "role": "assistant", "content": "Hi" },
+        ]));
+
+        assert!(check_supported(&Provider::Claude, &leading, true).is_err());
+        assert!(check_supported(&Provider::Claude, &leading, false).is_ok());
+        assert!(check_supported(&Provider::Claude, &trailing, true).is_ok());
+        assert!(check_supported(&Provider::Gemini, &trailing, true).is_err());
+        assert!(check_supported(&Provider::Gemini, &leading, true).is_ok());
+    }
+
+    #[test]
+    fn stop_accepts_a_string_or_list() {
+        assert_eq!(stop_sequences(&serde_json::json!({ "stop": "END" })), Some(serde_json::json!(["END"])));
+        assert_eq!(stop_sequences(&serde_json::json!({ "stop": [] })), None);
+    }
+
+    #[test]
+    fn max_output_tokens_skips_null() {
+        let req = serde_json::json!({ "max_completion_tokens": null, "max_tokens": 512 });
+
+        assert_eq!(max_output_tokens(&req), Some(&serde_json::json!(512)));
+    }
+}
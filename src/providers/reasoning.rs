@@ -0,0 +1,73 @@
+use serde_json::Value;
+
+use super::ModelRegistry;
+use crate::accounts::Provider;
+
+/// Model suffix enabling reasoning at the default level (`claude-sonnet-4:thinking`)
+const THINKING_SUFFIX: &str = ":thinking";
+
+/// Effort used for the `:thinking` suffix. Requests with neither a suffix nor
+/// `reasoning_effort` send no thinking settings, leaving the provider default.
+const DEFAULT_EFFORT: &str = "medium";
+
+/// Split a reasoning suffix off a model name.
+///
+/// `claude-sonnet-4:thinking` always splits. `o3-high` only splits when the
+/// registry knows `o3` and lists `high` among its reasoning levels, so model
+/// names that happen to end in a level are left alone.
+pub fn split_effort_suffix(registry: &ModelRegistry, provider: &Provider, model: &str) -> (String, Option<String>) {
+    if let Some(base) = model.strip_suffix(THINKING_SUFFIX) {
+        return (base.to_string(), Some(DEFAULT_EFFORT.to_string()));
+    }
+
+    if registry.find(provider, model).is_none() {
+        if let Some((base, level)) = model.rsplit_once('-') {
+            let known_level = registry
+                .find(provider, base)
+                .is_some_and(|info| info.reasoning_levels.iter().any(|l| l == level));
+            if known_level {
+                return (base.to_string(), Some(level.to_string()));
+            }
+        }
+    }
+
+    (model.to_string(), None)
+}
+
+/// Requested `reasoning_effort`, if any
+pub fn requested_effort(request: &Value) -> Option<&str> {
+    request.get("reasoning_effort").and_then(|e| e.as_str())
+}
+
+/// Thinking token budget for an OpenAI `reasoning_effort` level, used for
+/// Anthropic `thinking.budget_tokens` and Gemini `thinkingConfig.thinkingBudget`
+pub fn thinking_budget(effort: &str) -> Option<u32> {
+    match effort {
+        "low" => Some(2048),
+        "medium" => Some(8192),
+        "high" => Some(24576),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budgets_cover_the_documented_levels_only() {
+        assert_eq!(thinking_budget("low"), Some(2048));
+        assert_eq!(thinking_budget("medium"), Some(8192));
+        assert_eq!(thinking_budget("high"), Some(24576));
+        assert_eq!(thinking_budget("minimal"), None);
+    }
+
+    #[test]
+    fn thinking_suffix_uses_the_default_effort() {
+        let (model, effort) = split_effort_suffix(&ModelRegistry::default(), &Provider::Claude, "claude-sonnet-4:thinking");
+
+        assert_eq!(model, "claude-sonnet-4");
+        assert_eq!(effort.as_deref(), Some(DEFAULT_EFFORT));
+        assert!(thinking_budget(DEFAULT_EFFORT).is_some());
+    }
+}
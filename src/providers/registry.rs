@@ -1,23 +1,121 @@
 use std::path::PathBuf;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
+use super::{configured_models, params, reasoning, ClaudeProvider, CodexProvider, GeminiProvider, ProxyError};
+use crate::accounts::{AccountManager, Provider};
 use crate::config::Config;
+use crate::http::HttpClients;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ModelInfo {
     pub name: String,
     #[serde(default)]
     pub reasoning_levels: Vec<String>,
+    /// Maximum prompt tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    /// Supported features; `None` when unknown, which skips capability checks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<Pricing>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Vision,
+    Tools,
+    JsonMode,
+    Reasoning,
+}
+
+/// List prices in USD per million tokens
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Pricing {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl ModelInfo {
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_none_or(|caps| caps.contains(&capability))
+    }
+
+    /// Reject a chat completion request this model can't serve, before it is
+    /// sent upstream
+    pub fn check_request(&self, request: &Value) -> Result<(), ProxyError> {
+        if let Some(context_length) = self.context_length {
+            let estimated = estimate_prompt_tokens(request);
+            if estimated > context_length as usize {
+                return Err(ProxyError::InvalidRequest(format!(
+                    "Prompt of ~{} tokens exceeds the {} token context window of {}",
+                    estimated, context_length, self.name
+                )));
+            }
+        }
+
+        let requested_output = params::max_output_tokens(request).and_then(|t| t.as_u64());
+        if let (Some(requested), Some(max)) = (requested_output, self.max_output_tokens) {
+            if requested > max as u64 {
+                return Err(ProxyError::InvalidRequest(format!(
+                    "max_tokens {} exceeds the {} token output limit of {}",
+                    requested, max, self.name
+                )));
+            }
+        }
+
+        let needs = [
+            (Capability::Tools, has_tools(request), "tools"),
+            (Capability::Vision, has_images(request), "image input"),
+            (Capability::JsonMode, has_json_format(request), "response_format"),
+            (Capability::Reasoning, reasoning::requested_effort(request).is_some(), "reasoning_effort"),
+        ];
+        for (capability, needed, feature) in needs {
+            if needed && !self.supports(capability) {
+                return Err(ProxyError::InvalidRequest(format!("{} does not support {}", self.name, feature)));
+            }
+        }
+
+        if let Some(effort) = reasoning::requested_effort(request) {
+            if !self.reasoning_levels.is_empty() && !self.reasoning_levels.iter().any(|l| l == effort) {
+                return Err(ProxyError::InvalidRequest(format!(
+                    "Invalid reasoning_effort '{}' for {}; expected one of: {}",
+                    effort, self.name, self.reasoning_levels.join(", ")
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ModelRegistry {
+    /// When the lists were last fetched from the providers
+    #[serde(default)]
+    pub fetched_at: Option<DateTime<Utc>>,
     pub codex: Vec<ModelInfo>,
     pub claude: Vec<ModelInfo>,
     pub gemini: Vec<ModelInfo>,
 }
 
+/// A model as listed by `omniproxy models` and `/v1/models`
+#[derive(Debug, Clone)]
+pub struct ListedModel {
+    pub name: String,
+    pub provider: Provider,
+    /// Whether this comes from the `[models]` table rather than the provider
+    pub alias: bool,
+}
+
 impl ModelRegistry {
     fn path() -> anyhow::Result<PathBuf> {
         Ok(Config::dir()?.join("models.json"))
@@ -28,20 +126,154 @@ impl ModelRegistry {
 
         if path.exists() {
             let content = std::fs::read_to_string(&path)?;
-            Ok(serde_json::from_str(&content)?)
+            let mut registry: Self = serde_json::from_str(&content)?;
+            registry.fill_known_metadata();
+            Ok(registry)
         } else {
             Ok(Self::default_registry())
         }
     }
 
-    pub async fn refresh() -> anyhow::Result<Self> {
-        // For now, return default registry
-        // In the future, could fetch from provider APIs
-        let registry = Self::default_registry();
-        registry.save()?;
+    /// Look up a model, accepting an undated name for a dated snapshot
+    /// (`claude-opus-4` finds `claude-opus-4-20250514`)
+    pub fn find(&self, provider: &Provider, model: &str) -> Option<&ModelInfo> {
+        let models = self.models(provider);
+
+        models.iter().find(|m| m.name == model).or_else(|| {
+            models.iter().find(|m| {
+                m.name
+                    .strip_prefix(model)
+                    .and_then(|rest| rest.strip_prefix('-'))
+                    .is_some_and(|date| !date.is_empty() && date.chars().all(|c| c.is_ascii_digit() || c == '-'))
+            })
+        })
+    }
+
+    /// Fill metadata missing from cached or discovered entries with the built-in values
+    fn fill_known_metadata(&mut self) {
+        let defaults = Self::default_registry();
+
+        for provider in [Provider::Codex, Provider::Claude, Provider::Gemini] {
+            for model in self.models_mut(&provider) {
+                if let Some(known) = defaults.models(&provider).iter().find(|m| m.name == model.name) {
+                    if model.reasoning_levels.is_empty() {
+                        model.reasoning_levels = known.reasoning_levels.clone();
+                    }
+                    model.context_length = model.context_length.or(known.context_length);
+                    model.max_output_tokens = model.max_output_tokens.or(known.max_output_tokens);
+                    model.capabilities = model.capabilities.take().or_else(|| known.capabilities.clone());
+                    model.pricing = model.pricing.or(known.pricing);
+                }
+            }
+        }
+    }
+
+    /// Load the cached registry, refreshing it first if it is older than the TTL
+    pub async fn load_or_refresh(config: &Config, accounts: &AccountManager, clients: &HttpClients) -> anyhow::Result<Self> {
+        let registry = Self::load()?;
+
+        if registry.is_stale(config) {
+            registry.refresh(accounts, clients).await
+        } else {
+            Ok(registry)
+        }
+    }
+
+    pub fn is_stale(&self, config: &Config) -> bool {
+        match self.fetched_at {
+            Some(fetched_at) => Utc::now() - fetched_at >= chrono::Duration::seconds(config.registry.ttl_secs as i64),
+            None => true,
+        }
+    }
+
+    /// Fetch model lists from each provider using a valid pooled account.
+    ///
+    /// Providers without a usable account, or whose listing fails, keep their
+    /// current entries.
+    pub async fn refresh(&self, accounts: &AccountManager, clients: &HttpClients) -> anyhow::Result<Self> {
+        let mut registry = self.clone();
+        let mut fetched = false;
+
+        for provider in [Provider::Codex, Provider::Claude, Provider::Gemini] {
+            let Some(account) = accounts.next_account(&provider).await else {
+                tracing::debug!("No valid {} account, keeping cached model list", provider);
+                continue;
+            };
+
+            let client = match clients.for_account(&account) {
+                Ok(client) => client,
+                Err(e) => {
+                    tracing::warn!("Failed to list {} models: {}", provider, e);
+                    continue;
+                }
+            };
+            let result = match provider {
+                Provider::Codex => CodexProvider::list_models(&client, &account, clients.config()).await,
+                Provider::Claude => ClaudeProvider::list_models(&client, &account, clients.config()).await,
+                Provider::Gemini => GeminiProvider::list_models(&client, &account, clients.config()).await,
+            };
+
+            match result {
+                Ok(models) if !models.is_empty() => {
+                    let current = registry.models_mut(&provider);
+                    *current = merge_models(current, models);
+                    fetched = true;
+                }
+                Ok(_) => tracing::warn!("{} returned no models, keeping cached list", provider),
+                Err(e) => tracing::warn!("Failed to list {} models: {}", provider, e),
+            }
+        }
+
+        registry.fill_known_metadata();
+
+        // Without any account there is nothing cached worth keeping for a full TTL
+        if fetched {
+            registry.fetched_at = Some(Utc::now());
+            registry.save()?;
+        }
+
         Ok(registry)
     }
 
+    /// Provider models followed by `[models]` aliases, for providers accepted by `include`
+    pub fn list(&self, config: &Config, include: impl Fn(&Provider) -> bool) -> Vec<ListedModel> {
+        let mut listed = Vec::new();
+
+        for provider in [Provider::Codex, Provider::Claude, Provider::Gemini] {
+            if include(&provider) {
+                listed.extend(self.models(&provider).iter().map(|m| ListedModel {
+                    name: m.name.clone(),
+                    provider,
+                    alias: false,
+                }));
+            }
+        }
+
+        for (name, provider) in configured_models(config) {
+            if include(&provider) {
+                listed.push(ListedModel { name, provider, alias: true });
+            }
+        }
+
+        listed
+    }
+
+    pub fn models(&self, provider: &Provider) -> &[ModelInfo] {
+        match provider {
+            Provider::Codex => &self.codex,
+            Provider::Claude => &self.claude,
+            Provider::Gemini => &self.gemini,
+        }
+    }
+
+    fn models_mut(&mut self, provider: &Provider) -> &mut Vec<ModelInfo> {
+        match provider {
+            Provider::Codex => &mut self.codex,
+            Provider::Claude => &mut self.claude,
+            Provider::Gemini => &mut self.gemini,
+        }
+    }
+
     fn save(&self) -> anyhow::Result<()> {
         let path = Self::path()?;
 
@@ -55,89 +287,150 @@ impl ModelRegistry {
     }
 
     fn default_registry() -> Self {
+        use Capability::*;
+
         Self {
+            fetched_at: None,
             codex: vec![
-                ModelInfo {
-                    name: "gpt-4o".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "gpt-4o-mini".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "gpt-4-turbo".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "o1".to_string(),
-                    reasoning_levels: vec!["low".to_string(), "medium".to_string(), "high".to_string()],
-                },
-                ModelInfo {
-                    name: "o1-mini".to_string(),
-                    reasoning_levels: vec!["low".to_string(), "medium".to_string(), "high".to_string()],
-                },
-                ModelInfo {
-                    name: "o1-preview".to_string(),
-                    reasoning_levels: vec!["low".to_string(), "medium".to_string(), "high".to_string()],
-                },
-                ModelInfo {
-                    name: "o3-mini".to_string(),
-                    reasoning_levels: vec!["low".to_string(), "medium".to_string(), "high".to_string()],
-                },
+                model("gpt-4o", 128_000, 16_384, &[Vision, Tools, JsonMode], Some((2.50, 10.00))),
+                model("gpt-4o-mini", 128_000, 16_384, &[Vision, Tools, JsonMode], Some((0.15, 0.60))),
+                model("gpt-4-turbo", 128_000, 4_096, &[Vision, Tools, JsonMode], Some((10.00, 30.00))),
+                model("o1", 200_000, 100_000, &[Vision, Tools, JsonMode, Reasoning], Some((15.00, 60.00))),
+                model("o1-mini", 128_000, 65_536, &[Reasoning], Some((1.10, 4.40))),
+                model("o1-preview", 128_000, 32_768, &[Reasoning], Some((15.00, 60.00))),
+                model("o3-mini", 200_000, 100_000, &[Tools, JsonMode, Reasoning], Some((1.10, 4.40))),
             ],
             claude: vec![
-                ModelInfo {
-                    name: "claude-sonnet-4-20250514".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "claude-opus-4-20250514".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "claude-3-5-sonnet-20241022".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "claude-3-5-haiku-20241022".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "claude-3-opus-20240229".to_string(),
-                    reasoning_levels: vec![],
-                },
+                model("claude-sonnet-4-20250514", 200_000, 64_000, &[Vision, Tools, JsonMode, Reasoning], Some((3.00, 15.00))),
+                model("claude-opus-4-20250514", 200_000, 32_000, &[Vision, Tools, JsonMode, Reasoning], Some((15.00, 75.00))),
+                model("claude-3-5-sonnet-20241022", 200_000, 8_192, &[Vision, Tools, JsonMode], Some((3.00, 15.00))),
+                model("claude-3-5-haiku-20241022", 200_000, 8_192, &[Tools, JsonMode], Some((0.80, 4.00))),
+                model("claude-3-opus-20240229", 200_000, 4_096, &[Vision, Tools, JsonMode], Some((15.00, 75.00))),
             ],
             gemini: vec![
-                ModelInfo {
-                    name: "gemini-2.0-flash".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "gemini-2.0-flash-thinking".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "gemini-1.5-pro".to_string(),
-                    reasoning_levels: vec![],
-                },
-                ModelInfo {
-                    name: "gemini-1.5-flash".to_string(),
-                    reasoning_levels: vec![],
-                },
+                model("gemini-2.0-flash", 1_048_576, 8_192, &[Vision, Tools, JsonMode], Some((0.10, 0.40))),
+                model("gemini-2.0-flash-thinking", 1_048_576, 65_536, &[Vision, Reasoning], None),
+                model("gemini-1.5-pro", 2_097_152, 8_192, &[Vision, Tools, JsonMode], Some((1.25, 5.00))),
+                model("gemini-1.5-flash", 1_048_576, 8_192, &[Vision, Tools, JsonMode], Some((0.075, 0.30))),
             ],
         }
     }
+}
+
+/// Replace a model list with freshly fetched models, keeping metadata the
+/// provider listing doesn't include
+fn merge_models(current: &[ModelInfo], fetched: Vec<ModelInfo>) -> Vec<ModelInfo> {
+    fetched
+        .into_iter()
+        .map(|model| match current.iter().find(|m| m.name == model.name) {
+            Some(known) => ModelInfo {
+                name: model.name,
+                reasoning_levels: known.reasoning_levels.clone(),
+                context_length: model.context_length.or(known.context_length),
+                max_output_tokens: model.max_output_tokens.or(known.max_output_tokens),
+                capabilities: model.capabilities.or_else(|| known.capabilities.clone()),
+                pricing: model.pricing.or(known.pricing),
+            },
+            None => model,
+        })
+        .collect()
+}
+
+/// Rough prompt size: ~4 characters per token over all message text
+fn estimate_prompt_tokens(request: &Value) -> usize {
+    fn text_len(value: &Value) -> usize {
+        match value {
+            Value::String(s) => s.len(),
+            Value::Array(items) => items.iter().map(text_len).sum(),
+            Value::Object(obj) => obj.get("text").map(text_len).unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    let chars: usize = request
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|messages| messages.iter().filter_map(|m| m.get("content")).map(text_len).sum())
+        .unwrap_or(0);
+
+    chars / 4
+}
+
+fn has_tools(request: &Value) -> bool {
+    ["tools", "functions"]
+        .iter()
+        .any(|key| request.get(key).and_then(|t| t.as_array()).is_some_and(|t| !t.is_empty()))
+}
+
+fn has_images(request: &Value) -> bool {
+    request
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m.get("content").and_then(|c| c.as_array()))
+        .flatten()
+        .any(|part| part.get("type").and_then(|t| t.as_str()) == Some("image_url"))
+}
+
+fn has_json_format(request: &Value) -> bool {
+    matches!(
+        request.get("response_format").and_then(|f| f.get("type")).and_then(|t| t.as_str()),
+        Some("json_object") | Some("json_schema")
+    )
+}
+
+/// Built-in registry entry
+fn model(
+    name: &str,
+    context_length: u32,
+    max_output_tokens: u32,
+    capabilities: &[Capability],
+    pricing: Option<(f64, f64)>,
+) -> ModelInfo {
+    let reasoning_levels = if capabilities.contains(&Capability::Reasoning) {
+        vec!["low".to_string(), "medium".to_string(), "high".to_string()]
+    } else {
+        vec![]
+    };
+
+    ModelInfo {
+        name: name.to_string(),
+        reasoning_levels,
+        context_length: Some(context_length),
+        max_output_tokens: Some(max_output_tokens),
+        capabilities: Some(capabilities.to_vec()),
+        pricing: pricing.map(|(input, output)| Pricing { input, output }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn codex_models(&self) -> &[ModelInfo] {
-        &self.codex
+    fn limited_model() -> ModelInfo {
+        ModelInfo {
+            name: "test-model".to_string(),
+            reasoning_levels: vec!["low".to_string(), "high".to_string()],
+            max_output_tokens: Some(1000),
+            capabilities: Some(vec![Capability::Reasoning]),
+            ..Default::default()
+        }
     }
 
-    pub fn claude_models(&self) -> &[ModelInfo] {
-        &self.claude
+    #[test]
+    fn output_limit_reads_max_tokens_past_a_null() {
+        let request = serde_json::json!({ "max_completion_tokens": null, "max_tokens": 999999 });
+
+        assert!(matches!(limited_model().check_request(&request), Err(ProxyError::InvalidRequest(_))));
+        assert!(limited_model().check_request(&serde_json::json!({ "max_tokens": 1000 })).is_ok());
     }
 
-    pub fn gemini_models(&self) -> &[ModelInfo] {
-        &self.gemini
+    #[test]
+    fn checks_capabilities_and_reasoning_levels() {
+        assert!(limited_model().check_request(&serde_json::json!({ "reasoning_effort": "high" })).is_ok());
+        assert!(limited_model().check_request(&serde_json::json!({ "reasoning_effort": "medium" })).is_err());
+        assert!(limited_model().check_request(&serde_json::json!({ "response_format": { "type": "json_object" } })).is_err());
+        assert!(ModelInfo::default().check_request(&serde_json::json!({ "tools": [{}] })).is_ok());
     }
 }
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+
+use crate::accounts::Provider;
+use crate::config::{Config, ModelRoute};
+
+/// Maximum alias hops before giving up (guards against alias cycles)
+const MAX_ALIAS_DEPTH: usize = 8;
+
+/// Where a requested model is routed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelTarget {
+    pub provider: Provider,
+    /// Model id sent upstream
+    pub model: String,
+    /// Account pinned with `provider/model@account`
+    pub account: Option<String>,
+}
+
+/// Resolve a requested model name to a provider, upstream model id and
+/// optionally a pinned account.
+///
+/// `provider/model` and `provider/model@account` bypass lookup entirely and
+/// forward `model` as-is. Otherwise the `[models]` table from config.toml is
+/// consulted first (exact names before glob patterns), falling back to the
+/// name heuristics in `Provider::matches_model`.
+pub fn resolve_model(config: &Config, model: &str) -> anyhow::Result<ModelTarget> {
+    if let Some(target) = parse_prefixed(model)? {
+        return Ok(target);
+    }
+
+    lookup_model(config, model).ok_or_else(|| anyhow::anyhow!("Unknown model: {}", model))
+}
+
+/// Parse `provider/model[@account]`.
+///
+/// Returns `None` when there is no recognizable provider prefix, so names that
+/// merely contain a slash still go through normal lookup.
+fn parse_prefixed(model: &str) -> anyhow::Result<Option<ModelTarget>> {
+    let Some((prefix, rest)) = model.split_once('/') else {
+        return Ok(None);
+    };
+
+    let provider = match Provider::from_str(prefix) {
+        Ok(provider) => provider,
+        // `kind:instance/model` is unambiguously a provider prefix
+        Err(_) if prefix.contains(':') => anyhow::bail!("Unknown provider '{}' in model '{}'", prefix, model),
+        Err(_) => return Ok(None),
+    };
+
+    let (name, account) = match rest.rsplit_once('@') {
+        Some((name, account)) if !account.is_empty() => (name, Some(account.to_string())),
+        Some(_) => anyhow::bail!("Missing account name after '@' in model '{}'", model),
+        None => (rest, None),
+    };
+
+    if name.is_empty() {
+        anyhow::bail!("Missing model name after '{}/'", prefix);
+    }
+
+    Ok(Some(ModelTarget {
+        provider,
+        model: name.to_string(),
+        account,
+    }))
+}
+
+fn lookup_model(config: &Config, model: &str) -> Option<ModelTarget> {
+    let mut name = model.to_string();
+
+    for _ in 0..MAX_ALIAS_DEPTH {
+        match lookup(config, &name) {
+            Some(ModelRoute::Alias(target)) => name = target.clone(),
+            Some(ModelRoute::Target { provider, model }) => {
+                return Some(ModelTarget {
+                    provider: *provider,
+                    model: model.clone().unwrap_or(name),
+                    account: None,
+                });
+            }
+            None => {
+                return heuristic_provider(&name).map(|provider| ModelTarget {
+                    provider,
+                    model: name,
+                    account: None,
+                })
+            }
+        }
+    }
+
+    tracing::warn!("Alias chain for model '{}' is too deep or cyclic", model);
+    None
+}
+
+/// Non-glob `[models]` entries that resolve, for `/v1/models`
+pub fn configured_models(config: &Config) -> Vec<(String, Provider)> {
+    config
+        .models
+        .keys()
+        .filter(|name| !is_glob(name))
+        .filter_map(|name| lookup_model(config, name).map(|t| (name.clone(), t.provider)))
+        .collect()
+}
+
+fn lookup<'a>(config: &'a Config, name: &str) -> Option<&'a ModelRoute> {
+    match_model_key(&config.models, name)
+}
+
+/// Find the entry for a model in a table keyed by model name or glob pattern.
+///
+/// Exact (case-insensitive) names win; otherwise the most specific matching
+/// glob, the one with the most literal characters.
+pub fn match_model_key<'a, T>(table: &'a BTreeMap<String, T>, name: &str) -> Option<&'a T> {
+    if let Some((_, value)) = table
+        .iter()
+        .find(|(key, _)| !is_glob(key) && key.eq_ignore_ascii_case(name))
+    {
+        return Some(value);
+    }
+
+    table
+        .iter()
+        .filter(|(key, _)| is_glob(key) && glob_match(&key.to_lowercase(), &name.to_lowercase()))
+        .max_by_key(|(key, _)| key.chars().filter(|c| *c != '*' && *c != '?').count())
+        .map(|(_, value)| value)
+}
+
+fn heuristic_provider(model: &str) -> Option<Provider> {
+    [Provider::Codex, Provider::Claude, Provider::Gemini]
+        .into_iter()
+        .find(|p| p.matches_model(model))
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains('*') || pattern.contains('?')
+}
+
+/// Match `name` against a pattern where `*` matches any run and `?` one character
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut backtrack = None;
+
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ni));
+            pi += 1;
+        } else if let Some((star_pi, star_ni)) = backtrack {
+            pi = star_pi + 1;
+            ni = star_ni + 1;
+            backtrack = Some((star_pi, star_ni + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(models: &str) -> Config {
+        toml::from_str(&format!("[models]\n{}", models)).unwrap()
+    }
+
+    #[test]
+    fn glob_matches_stars_and_single_characters() {
+        assert!(glob_match("claude-*", "claude-sonnet-4"));
+        assert!(glob_match("*-mini", "o4-mini"));
+        assert!(glob_match("gpt-?o", "gpt-4o"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("gpt-?o", "gpt-40o"));
+        assert!(!glob_match("claude-*", "gemini-claude-x"));
+        assert!(!glob_match("a*b", "acbd"));
+    }
+
+    #[test]
+    fn exact_key_beats_the_most_specific_glob() {
+        let table: BTreeMap<String, u8> =
+            [("*".into(), 1), ("claude-*".into(), 2), ("claude-3-5-*".into(), 3), ("Claude-3-5-Haiku".into(), 4)]
+                .into_iter()
+                .collect();
+
+        assert_eq!(match_model_key(&table, "claude-3-5-haiku"), Some(&4));
+        assert_eq!(match_model_key(&table, "claude-3-5-sonnet"), Some(&3));
+        assert_eq!(match_model_key(&table, "CLAUDE-opus-4"), Some(&2));
+        assert_eq!(match_model_key(&table, "llama"), Some(&1));
+        assert_eq!(match_model_key(&BTreeMap::<String, u8>::new(), "llama"), None);
+    }
+
+    #[test]
+    fn aliases_resolve_to_targets() {
+        let config = config(
+            r#"
+            fast = "haiku"
+            haiku = { provider = "claude", model = "claude-3-5-haiku-latest" }
+            loop-a = "loop-b"
+            loop-b = "loop-a"
+            "#,
+        );
+
+        let target = resolve_model(&config, "fast").unwrap();
+        assert_eq!((target.provider, target.model.as_str()), (Provider::Claude, "claude-3-5-haiku-latest"));
+        assert!(resolve_model(&config, "loop-a").is_err());
+        assert!(resolve_model(&config, "unheard-of").is_err());
+        assert_eq!(configured_models(&config), vec![("fast".into(), Provider::Claude), ("haiku".into(), Provider::Claude)]);
+    }
+
+    #[test]
+    fn prefixed_model_pins_provider_and_account() {
+        let config = Config::default();
+
+        assert_eq!(
+            resolve_model(&config, "claude/my-model@work").unwrap(),
+            ModelTarget {
+                provider: Provider::Claude,
+                model: "my-model".into(),
+                account: Some("work".into()),
+            }
+        );
+        // Only the last `@` separates the account
+        let target = resolve_model(&config, "openai/user@host@acct").unwrap();
+        assert_eq!((target.model.as_str(), target.account.as_deref()), ("user@host", Some("acct")));
+        assert_eq!(resolve_model(&config, "gemini/gemini-2.5-pro").unwrap().account, None);
+    }
+
+    #[test]
+    fn malformed_prefixes_are_errors() {
+        let config = Config::default();
+
+        assert!(resolve_model(&config, "claude/model@").is_err());
+        assert!(resolve_model(&config, "claude/").is_err());
+        assert!(resolve_model(&config, "nope:x/model").is_err());
+    }
+
+    #[test]
+    fn unknown_prefix_goes_through_lookup() {
+        let config = config(r#""meta/*" = { provider = "codex" }"#);
+
+        let target = resolve_model(&config, "meta/llama-3").unwrap();
+        assert_eq!((target.provider, target.model.as_str()), (Provider::Codex, "meta/llama-3"));
+    }
+}
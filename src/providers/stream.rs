@@ -0,0 +1,246 @@
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap};
+use futures::StreamExt;
+use serde_json::Value;
+
+use crate::capture::{self, Capture};
+
+/// Translate an upstream server-sent event stream into OpenAI
+/// `chat.completion.chunk` events.
+///
+/// `translate` is called with each upstream `data:` payload and returns the
+/// chunks to emit for it (possibly none). The stream is terminated with
+/// `data: [DONE]` like OpenAI's. The raw upstream stream is copied to
+/// `capture` when there is one.
+pub fn translate_sse<F>(upstream: reqwest::Response, translate: F, capture: Option<Capture>) -> Body
+where
+    F: FnMut(Value) -> Vec<Value> + Send + 'static,
+{
+    struct State<S, F> {
+        upstream: S,
+        buffer: Vec<u8>,
+        translate: F,
+        capture: Option<(Capture, String)>,
+        done: bool,
+    }
+
+    let stage = capture::response_stage(capture::UPSTREAM_RESPONSE, upstream.headers());
+    let state = State {
+        upstream: upstream.bytes_stream(),
+        buffer: Vec::new(),
+        translate,
+        capture: capture.map(|c| (c, stage)),
+        done: false,
+    };
+
+    let stream = futures::stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+
+        loop {
+            // Emit complete lines already buffered
+            if let Some(pos) = state.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = state.buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+
+                let Some(data) = line.trim_end().strip_prefix("data:") else {
+                    continue;
+                };
+                let data = data.trim();
+                if data.is_empty() || data == "[DONE]" {
+                    continue;
+                }
+
+                match serde_json::from_str::<Value>(data) {
+                    Ok(event) => {
+                        let chunks = (state.translate)(event);
+                        if !chunks.is_empty() {
+                            return Some((Ok(encode(&chunks)), state));
+                        }
+                    }
+                    Err(e) => tracing::warn!("Skipping malformed upstream event: {}", e),
+                }
+                continue;
+            }
+
+            match state.upstream.next().await {
+                Some(Ok(bytes)) => {
+                    if let Some((capture, stage)) = &state.capture {
+                        capture.append(stage, bytes.clone());
+                    }
+                    state.buffer.extend_from_slice(&bytes);
+                }
+                Some(Err(e)) => {
+                    state.done = true;
+                    return Some((Err(std::io::Error::other(e)), state));
+                }
+                None => {
+                    state.done = true;
+                    return Some((Ok(Bytes::from_static(b"data: [DONE]\n\n")), state));
+                }
+            }
+        }
+    });
+
+    Body::from_stream(stream)
+}
+
+/// Whether a response is a server-sent event stream
+pub fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/event-stream"))
+}
+
+/// Set `model` in an OpenAI response, or in each chunk of a stream. The
+/// caller drops `Content-Length`, since the body's length may change.
+pub fn rewrite_model(body: Body, streaming: bool, model: &str) -> Body {
+    struct State<S> {
+        upstream: S,
+        buffer: Vec<u8>,
+        model: String,
+        streaming: bool,
+        done: bool,
+    }
+
+    let state = State {
+        upstream: body.into_data_stream(),
+        buffer: Vec::new(),
+        model: model.to_string(),
+        streaming,
+        done: false,
+    };
+
+    let stream = futures::stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+
+        loop {
+            match state.upstream.next().await {
+                Some(Ok(bytes)) => {
+                    state.buffer.extend_from_slice(&bytes);
+
+                    // Streams pass on complete lines as they arrive
+                    if state.streaming {
+                        if let Some(pos) = state.buffer.iter().rposition(|b| *b == b'\n') {
+                            let lines: Vec<u8> = state.buffer.drain(..=pos).collect();
+                            let rewritten = rewrite_lines(&lines, &state.model);
+                            return Some((Ok(Bytes::from(rewritten)), state));
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+                None => {
+                    state.done = true;
+                    let rest = std::mem::take(&mut state.buffer);
+                    if rest.is_empty() {
+                        return None;
+                    }
+                    let rewritten = if state.streaming {
+                        rewrite_lines(&rest, &state.model)
+                    } else {
+                        rewrite_json(&rest, &state.model).unwrap_or(rest)
+                    };
+                    return Some((Ok(Bytes::from(rewritten)), state));
+                }
+            }
+        }
+    });
+
+    Body::from_stream(stream)
+}
+
+/// Rewrite the `data:` lines of an event stream, leaving other lines as they are
+fn rewrite_lines(lines: &[u8], model: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(lines.len());
+    for line in lines.split_inclusive(|b| *b == b'\n') {
+        let rewritten = line
+            .strip_prefix(b"data:")
+            .and_then(|data| rewrite_json(data, model))
+            .map(|json| [b"data: ".as_slice(), &json, b"\n"].concat());
+        out.extend_from_slice(rewritten.as_deref().unwrap_or(line));
+    }
+    out
+}
+
+/// A JSON object with its `model` replaced; None when it has none
+fn rewrite_json(json: &[u8], model: &str) -> Option<Vec<u8>> {
+    let mut value: Value = serde_json::from_slice(json.trim_ascii()).ok()?;
+    let field = value.as_object_mut()?.get_mut("model")?;
+    *field = Value::String(model.to_string());
+    serde_json::to_vec(&value).ok()
+}
+
+/// Build an OpenAI `chat.completion.chunk`
+pub fn chunk(id: &str, model: &str, created: i64, delta: Value, finish_reason: Option<&str>) -> Value {
+    serde_json::json!({
+        "id": id,
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "delta": delta,
+            "finish_reason": finish_reason,
+        }],
+    })
+}
+
+fn encode(chunks: &[Value]) -> Bytes {
+    let mut out = String::new();
+    for chunk in chunks {
+        out.push_str("data: ");
+        out.push_str(&chunk.to_string());
+        out.push_str("\n\n");
+    }
+    Bytes::from(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+
+    use super::*;
+
+    async fn rewrite(chunks: &[&'static str], streaming: bool) -> String {
+        let chunks: Vec<_> = chunks.iter().map(|c| Ok::<_, std::io::Error>(Bytes::from_static(c.as_bytes()))).collect();
+        let body = rewrite_model(Body::from_stream(futures::stream::iter(chunks)), streaming, "gpt-5");
+        String::from_utf8(body.collect().await.unwrap().to_bytes().to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rewrites_model_of_a_response() {
+        let body = rewrite(&["{\n  \"model\": \"claude-sonnet-4-20250514\",", "\n  \"choices\": []\n}"], false).await;
+
+        let value: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["model"], "gpt-5");
+    }
+
+    #[tokio::test]
+    async fn rewrites_model_of_each_chunk() {
+        let body = rewrite(
+            &[
+                "data: {\"model\":\"a\",\"choices\":[]}\n\ndata: {\"mod",
+                "el\":\"a\",\"choices\":[]}\n\n: keep-alive\n\ndata: [DONE]\n\n",
+            ],
+            true,
+        )
+        .await;
+
+        assert_eq!(
+            body,
+            "data: {\"choices\":[],\"model\":\"gpt-5\"}\n\ndata: {\"choices\":[],\"model\":\"gpt-5\"}\n\n: keep-alive\n\ndata: [DONE]\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_bodies_without_model_alone() {
+        assert_eq!(rewrite(&["not json"], false).await, "not json");
+    }
+}
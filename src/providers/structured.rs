@@ -0,0 +1,199 @@
+use serde_json::{Map, Value};
+
+use super::ProxyError;
+
+/// Requested structured output, from an OpenAI `response_format`
+pub enum ResponseFormat {
+    /// Any JSON object (`json_object`)
+    JsonObject,
+    /// JSON matching a schema (`json_schema`)
+    JsonSchema {
+        name: String,
+        description: Option<String>,
+        schema: Value,
+    },
+}
+
+impl ResponseFormat {
+    /// Parse `response_format`; `None` for plain text
+    pub fn from_request(request: &Value) -> Result<Option<Self>, ProxyError> {
+        let Some(format) = request.get("response_format") else {
+            return Ok(None);
+        };
+
+        match format.get("type").and_then(|t| t.as_str()) {
+            None | Some("text") => Ok(None),
+            Some("json_object") => Ok(Some(Self::JsonObject)),
+            Some("json_schema") => {
+                let spec = format
+                    .get("json_schema")
+                    .ok_or_else(|| {
+                        ProxyError::InvalidRequest("response_format.json_schema is required for type 'json_schema'".into())
+                    })?;
+                let schema = spec
+                    .get("schema")
+                    .cloned()
+                    .ok_or_else(|| ProxyError::InvalidRequest("response_format.json_schema.schema is required".into()))?;
+
+                Ok(Some(Self::JsonSchema {
+                    name: spec.get("name").and_then(|n| n.as_str()).unwrap_or("response").to_string(),
+                    description: spec.get("description").and_then(|d| d.as_str()).map(String::from),
+                    schema,
+                }))
+            }
+            Some(other) => Err(ProxyError::InvalidRequest(format!("Unsupported response_format type: {}", other))),
+        }
+    }
+
+    /// The JSON schema to enforce (any object for `json_object`)
+    pub fn schema(&self) -> Value {
+        match self {
+            Self::JsonObject => serde_json::json!({ "type": "object" }),
+            Self::JsonSchema { schema, .. } => schema.clone(),
+        }
+    }
+}
+
+/// Convert a JSON schema to the OpenAPI subset Gemini accepts for `responseSchema`.
+///
+/// Gemini doesn't resolve references, so local `$ref`s (`#/$defs/...`) are
+/// inlined. Remote and recursive references can't be, and are an error.
+pub fn to_gemini_schema(schema: &Value) -> Result<Value, ProxyError> {
+    convert_schema(schema, schema, &mut Vec::new())
+}
+
+/// `expanding` holds the references being inlined above `node`, to catch cycles
+fn convert_schema<'a>(node: &'a Value, root: &'a Value, expanding: &mut Vec<&'a str>) -> Result<Value, ProxyError> {
+    match node {
+        Value::Object(obj) => {
+            let mut out = match obj.get("$ref").and_then(|r| r.as_str()) {
+                Some(reference) => match resolve_ref(reference, root, expanding)? {
+                    Value::Object(target) => target,
+                    _ => Map::new(),
+                },
+                None => Map::new(),
+            };
+
+            for (key, value) in obj {
+                match key.as_str() {
+                    // Not part of Gemini's schema subset, or already inlined
+                    "additionalProperties" | "$schema" | "$id" | "strict" | "default" | "$ref" | "$defs"
+                    | "definitions" => {}
+                    // `"type": ["string", "null"]` becomes `"type": "string", "nullable": true`
+                    "type" if value.is_array() => {
+                        let types: Vec<&str> = value.as_array().into_iter().flatten().filter_map(|t| t.as_str()).collect();
+                        if let Some(first) = types.iter().find(|t| **t != "null") {
+                            out.insert("type".to_string(), Value::from(*first));
+                        }
+                        if types.contains(&"null") {
+                            out.insert("nullable".to_string(), Value::Bool(true));
+                        }
+                    }
+                    "properties" => {
+                        let mut properties = Map::new();
+                        for (name, property) in value.as_object().into_iter().flatten() {
+                            properties.insert(name.clone(), convert_schema(property, root, expanding)?);
+                        }
+                        out.insert(key.clone(), Value::Object(properties));
+                    }
+                    _ => {
+                        out.insert(key.clone(), convert_schema(value, root, expanding)?);
+                    }
+                }
+            }
+
+            Ok(Value::Object(out))
+        }
+        Value::Array(items) => items.iter().map(|item| convert_schema(item, root, expanding)).collect(),
+        other => Ok(other.clone()),
+    }
+}
+
+/// The converted schema a local `$ref` points to
+fn resolve_ref<'a>(reference: &'a str, root: &'a Value, expanding: &mut Vec<&'a str>) -> Result<Value, ProxyError> {
+    let pointer = reference.strip_prefix('#').ok_or_else(|| {
+        ProxyError::InvalidRequest(format!("Gemini schemas can only use local $ref, not '{}'", reference))
+    })?;
+    if expanding.contains(&reference) {
+        return Err(ProxyError::InvalidRequest(format!(
+            "Gemini doesn't support recursive schemas ($ref '{}')",
+            reference
+        )));
+    }
+    let target = root
+        .pointer(pointer)
+        .ok_or_else(|| ProxyError::InvalidRequest(format!("Unresolved $ref '{}' in response_format schema", reference)))?;
+
+    expanding.push(reference);
+    let converted = convert_schema(target, root, expanding);
+    expanding.pop();
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_nullable_types_and_drops_unsupported_keys() {
+        let schema = serde_json::json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "type": { "type": ["string", "null"], "default": "x" },
+            },
+            "required": ["type"],
+        });
+
+        assert_eq!(
+            to_gemini_schema(&schema).unwrap(),
+            serde_json::json!({
+                "type": "object",
+                "properties": { "type": { "type": "string", "nullable": true } },
+                "required": ["type"],
+            })
+        );
+    }
+
+    #[test]
+    fn inlines_local_refs() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "home": { "$ref": "#/$defs/address", "description": "Where they live" },
+                "past": { "type": "array", "items": { "$ref": "#/definitions/address" } },
+            },
+            "$defs": { "address": { "type": "object", "properties": { "city": { "type": "string" } } } },
+            "definitions": { "address": { "$ref": "#/$defs/address" } },
+        });
+
+        let address = serde_json::json!({ "type": "object", "properties": { "city": { "type": "string" } } });
+        let mut home = address.clone();
+        home["description"] = Value::from("Where they live");
+        assert_eq!(
+            to_gemini_schema(&schema).unwrap(),
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "home": home,
+                    "past": { "type": "array", "items": address },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_refs_that_cannot_be_inlined() {
+        for schema in [
+            serde_json::json!({ "$ref": "https://example.com/schema.json" }),
+            serde_json::json!({ "properties": { "a": { "$ref": "#/$defs/missing" } } }),
+            serde_json::json!({
+                "$defs": { "node": { "type": "object", "properties": { "next": { "$ref": "#/$defs/node" } } } },
+                "$ref": "#/$defs/node",
+            }),
+        ] {
+            assert!(matches!(to_gemini_schema(&schema), Err(ProxyError::InvalidRequest(_))), "{}", schema);
+        }
+    }
+}
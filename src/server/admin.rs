@@ -0,0 +1,211 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, Request, Response, StatusCode},
+    middleware::{self, Next},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::accounts::{Account, Provider};
+
+use super::router::{error, AppState};
+
+type AdminResult = Result<Json<Value>, (StatusCode, Json<Value>)>;
+
+/// Routes under `/admin`, all requiring the admin token
+pub(super) fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/accounts", get(list_accounts))
+        .route("/accounts/{id}", get(get_account))
+        .route("/accounts/{id}/enable", post(enable_account))
+        .route("/accounts/{id}/disable", post(disable_account))
+        .route("/accounts/{id}/drain", post(drain_account))
+        .route("/accounts/{id}/refresh", post(refresh_account))
+        .route("/requests", get(list_requests))
+        .route("/stats", get(get_stats))
+        .route("/reload", post(reload))
+        .layer(middleware::from_fn_with_state(state, authorize))
+}
+
+/// Require `Authorization: Bearer <token>`. The admin API doesn't exist
+/// until a token is configured.
+async fn authorize(State(state): State<AppState>, request: Request<Body>, next: Next) -> Response<Body> {
+    let Some(token) = state.live.config().admin.token() else {
+        return error(StatusCode::NOT_FOUND, "Admin API is disabled; set [admin] token to enable it").into_response();
+    };
+
+    if !token_matches(request.headers(), &token) {
+        let mut response = error(StatusCode::UNAUTHORIZED, "Invalid admin token").into_response();
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+        return response;
+    }
+
+    next.run(request).await
+}
+
+/// Compare digests rather than the tokens, so the comparison time doesn't
+/// depend on how much of the token was guessed right
+fn token_matches(headers: &HeaderMap, token: &str) -> bool {
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    presented.is_some_and(|presented| Sha256::digest(presented.as_bytes()) == Sha256::digest(token.as_bytes()))
+}
+
+async fn list_accounts(State(state): State<AppState>) -> Json<Value> {
+    let mut accounts = Vec::new();
+    for provider in [Provider::Codex, Provider::Claude, Provider::Gemini] {
+        for account in state.account_manager.list(&provider).await {
+            accounts.push(account_status(&state, &account));
+        }
+    }
+
+    Json(json!({ "accounts": accounts }))
+}
+
+async fn get_account(State(state): State<AppState>, Path(id): Path<String>) -> AdminResult {
+    let account = find_account(&state, &id).await?;
+    Ok(Json(account_status(&state, &account)))
+}
+
+async fn enable_account(State(state): State<AppState>, Path(id): Path<String>) -> AdminResult {
+    set_disabled(&state, &id, false).await
+}
+
+async fn disable_account(State(state): State<AppState>, Path(id): Path<String>) -> AdminResult {
+    set_disabled(&state, &id, true).await
+}
+
+async fn set_disabled(state: &AppState, id: &str, disabled: bool) -> AdminResult {
+    let account = find_account(state, id).await?;
+    state
+        .account_manager
+        .set_disabled(&account.provider, &account.name, disabled)
+        .await
+        .map_err(|e| error(StatusCode::NOT_FOUND, e.to_string()))?;
+    save_accounts(state).await?;
+
+    tracing::info!("{} account {}", if disabled { "Disabled" } else { "Enabled" }, id);
+    get_account(State(state.clone()), Path(id.to_string())).await
+}
+
+async fn drain_account(State(state): State<AppState>, Path(id): Path<String>) -> AdminResult {
+    let account = find_account(&state, &id).await?;
+    state
+        .account_manager
+        .drain(&account.provider, &account.name)
+        .await
+        .map_err(|e| error(StatusCode::NOT_FOUND, e.to_string()))?;
+
+    tracing::info!("Draining account {}", id);
+    Ok(Json(account_status(&state, &account)))
+}
+
+async fn refresh_account(State(state): State<AppState>, Path(id): Path<String>) -> AdminResult {
+    let account = find_account(&state, &id).await?;
+    super::refresh_account(&state.account_manager, &state.live.clients(), &account)
+        .await
+        .map_err(|e| error(StatusCode::BAD_GATEWAY, format!("Failed to refresh token for {}: {}", id, e)))?;
+    save_accounts(&state).await?;
+
+    get_account(State(state), Path(id)).await
+}
+
+async fn list_requests(State(state): State<AppState>) -> Json<Value> {
+    let now = chrono::Utc::now();
+    let requests: Vec<Value> = state
+        .in_flight
+        .list()
+        .into_iter()
+        .map(|request| {
+            let elapsed_ms = (now - request.started_at).num_milliseconds();
+            let mut value = json!(request);
+            value["elapsed_ms"] = json!(elapsed_ms);
+            value
+        })
+        .collect();
+
+    Json(json!({ "requests": requests }))
+}
+
+/// Per-model usage, request rates and recent errors since the server started
+async fn get_stats(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "models": state.stats.models(),
+        "requests_per_minute": state.stats.rates(),
+        "recent_errors": state.stats.errors(),
+    }))
+}
+
+/// Reload config.toml and accounts.json, as on SIGHUP
+async fn reload(State(state): State<AppState>) -> AdminResult {
+    super::reload::reload(&state.account_manager, &state.live, true, true)
+        .await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+/// Look up an account by its `provider:name` ID
+async fn find_account(state: &AppState, id: &str) -> Result<Account, (StatusCode, Json<Value>)> {
+    let (provider, name) = id
+        .split_once(':')
+        .ok_or_else(|| error(StatusCode::BAD_REQUEST, "Invalid account ID. Use: provider:name"))?;
+    let provider = Provider::from_str(provider).map_err(|e| error(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    state
+        .account_manager
+        .get(&provider, name)
+        .await
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, format!("Account not found: {}", id)))
+}
+
+async fn save_accounts(state: &AppState) -> Result<(), (StatusCode, Json<Value>)> {
+    state
+        .account_manager
+        .save()
+        .await
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save accounts: {}", e)))
+}
+
+/// An account's health, rotation state and usage
+fn account_status(state: &AppState, account: &Account) -> Value {
+    let manager = &state.account_manager;
+    let draining = manager.is_draining(account);
+    let cooldown = manager.cooldown_remaining(account);
+
+    let status = if account.disabled {
+        "disabled"
+    } else if draining {
+        "draining"
+    } else if !account.is_valid() {
+        "expired"
+    } else if cooldown.is_some() {
+        "cooling_down"
+    } else {
+        "available"
+    };
+
+    json!({
+        "id": format!("{}:{}", account.provider, account.name),
+        "provider": account.provider,
+        "name": account.name,
+        "email": account.credentials.email,
+        "status": status,
+        "disabled": account.disabled,
+        "draining": draining,
+        "token_expires_at": account.credentials.expires_at,
+        "token_expires_in_secs": (account.credentials.expires_at - chrono::Utc::now()).num_seconds(),
+        "cooldown_remaining_secs": cooldown.map(|d| d.as_secs()),
+        "in_flight": state.in_flight.count(account.provider.as_str(), &account.name),
+        "usage": manager.usage(account),
+    })
+}
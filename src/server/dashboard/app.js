@@ -0,0 +1,220 @@
+// Omniproxy dashboard: polls the admin API and renders it without any libraries.
+
+const POLL_MS = 5000;
+const TOKEN_KEY = "omniproxy-admin-token";
+
+let accounts = [];
+let pollTimer = null;
+
+const $ = (id) => document.getElementById(id);
+
+function token() {
+  return sessionStorage.getItem(TOKEN_KEY);
+}
+
+async function api(path, method = "GET") {
+  const response = await fetch("/admin" + path, {
+    method,
+    headers: { Authorization: "Bearer " + token() },
+  });
+  const body = await response.json().catch(() => ({}));
+  if (!response.ok) {
+    const error = new Error(body.error?.message || response.statusText);
+    error.status = response.status;
+    throw error;
+  }
+  return body;
+}
+
+function el(tag, attrs = {}, ...children) {
+  const node = document.createElement(tag);
+  for (const [key, value] of Object.entries(attrs)) {
+    if (key === "class") node.className = value;
+    else if (key.startsWith("on")) node.addEventListener(key.slice(2), value);
+    else node.setAttribute(key, value);
+  }
+  for (const child of children) {
+    node.append(child instanceof Node ? child : document.createTextNode(child ?? ""));
+  }
+  return node;
+}
+
+function svg(tag, attrs) {
+  const node = document.createElementNS("http://www.w3.org/2000/svg", tag);
+  for (const [key, value] of Object.entries(attrs)) node.setAttribute(key, value);
+  return node;
+}
+
+function formatDuration(secs) {
+  const sign = secs < 0 ? "-" : "";
+  secs = Math.abs(Math.floor(secs));
+  const d = Math.floor(secs / 86400);
+  const h = Math.floor((secs % 86400) / 3600);
+  const m = Math.floor((secs % 3600) / 60);
+  const s = secs % 60;
+  if (d > 0) return `${sign}${d}d ${h}h`;
+  if (h > 0) return `${sign}${h}h ${m}m`;
+  if (m > 0) return `${sign}${m}m ${s}s`;
+  return `${sign}${s}s`;
+}
+
+function formatNumber(n) {
+  if (n >= 1e9) return (n / 1e9).toFixed(1) + "B";
+  if (n >= 1e6) return (n / 1e6).toFixed(1) + "M";
+  if (n >= 1e3) return (n / 1e3).toFixed(1) + "k";
+  return String(n);
+}
+
+// Token expiry, recomputed every second so the countdown ticks between polls
+function expiryCell(account) {
+  const secs = (new Date(account.token_expires_at) - Date.now()) / 1000;
+  const cls = secs <= 0 ? "expired-time" : secs < 600 ? "expiring" : "";
+  return el("td", { class: "expiry " + cls, "data-expires": account.token_expires_at },
+    secs <= 0 ? `expired ${formatDuration(-secs)} ago` : formatDuration(secs));
+}
+
+async function act(account, action) {
+  try {
+    await api(`/accounts/${encodeURIComponent(account.id)}/${action}`, "POST");
+  } catch (e) {
+    alert(`${action} ${account.id}: ${e.message}`);
+  }
+  refresh();
+}
+
+function renderAccounts() {
+  const rows = accounts.map((a) => {
+    const actions = el("td", { class: "actions" },
+      a.disabled || a.draining
+        ? el("button", { onclick: () => act(a, "enable") }, "Enable")
+        : el("button", { onclick: () => act(a, "disable") }, "Disable"),
+      " ",
+      el("button", { onclick: () => act(a, "drain"), ...(a.draining || a.disabled ? { disabled: "" } : {}) }, "Drain"),
+      " ",
+      el("button", { onclick: () => act(a, "refresh") }, "Refresh token"),
+    );
+    return el("tr", {},
+      el("td", { title: a.email || "" }, a.id),
+      el("td", { class: "status " + a.status }, a.status.replace("_", " ")),
+      expiryCell(a),
+      el("td", { class: "num" }, a.cooldown_remaining_secs != null ? formatDuration(a.cooldown_remaining_secs) : "–"),
+      el("td", { class: "num" }, String(a.in_flight)),
+      el("td", { class: "num" }, formatNumber(a.usage.requests)),
+      el("td", { class: "num", title: a.usage.last_error || "" }, formatNumber(a.usage.errors)),
+      el("td", { class: "num" }, formatNumber(a.usage.prompt_tokens + a.usage.completion_tokens)),
+      actions,
+    );
+  });
+  $("accounts").replaceChildren(...(rows.length ? rows : [el("tr", {}, el("td", { colspan: 9, class: "empty" }, "No accounts"))]));
+}
+
+function tickCountdowns() {
+  for (const cell of document.querySelectorAll("td.expiry")) {
+    const account = accounts.find((a) => a.token_expires_at === cell.dataset.expires);
+    if (account) cell.replaceWith(expiryCell(account));
+  }
+}
+
+function renderRates(buckets) {
+  const chart = $("rates");
+  const width = 600, height = 120;
+  const max = Math.max(1, ...buckets.map((b) => b.requests));
+  const slot = width / buckets.length;
+
+  const bars = [];
+  buckets.forEach((bucket, i) => {
+    const x = i * slot + 1;
+    const okHeight = ((bucket.requests - bucket.errors) / max) * (height - 4);
+    const failedHeight = (bucket.errors / max) * (height - 4);
+    const title = svg("title", {});
+    title.textContent = `${new Date(bucket.minute).toLocaleTimeString()}: ${bucket.requests} requests, ${bucket.errors} failed`;
+
+    const group = svg("g", {});
+    group.append(
+      title,
+      svg("rect", { class: "ok", x, y: height - okHeight, width: slot - 2, height: okHeight }),
+      svg("rect", { class: "failed", x, y: height - okHeight - failedHeight, width: slot - 2, height: failedHeight }),
+      // Full-height target so the tooltip works on idle minutes too
+      svg("rect", { x, y: 0, width: slot - 2, height, fill: "transparent" }),
+    );
+    bars.push(group);
+  });
+  chart.replaceChildren(...bars);
+}
+
+function renderModels(models) {
+  const entries = Object.entries(models).sort((a, b) => b[1].requests - a[1].requests);
+  const max = Math.max(1, ...entries.map(([, m]) => m.prompt_tokens + m.completion_tokens));
+
+  const rows = entries.map(([name, m]) => {
+    const tokens = m.prompt_tokens + m.completion_tokens;
+    return el("div", { class: "bar-row" },
+      el("span", { class: "name", title: name }, name),
+      el("div", { class: "track" }, el("div", { class: "fill", style: `width: ${(tokens / max) * 100}%` })),
+      el("span", { class: "figures" },
+        `${formatNumber(m.requests)} req · ${formatNumber(m.errors)} err · ` +
+        `${formatNumber(m.prompt_tokens)} in / ${formatNumber(m.completion_tokens)} out`),
+    );
+  });
+  $("models").replaceChildren(...(rows.length ? rows : [el("p", { class: "empty" }, "No requests yet")]));
+}
+
+function renderErrors(errors) {
+  const rows = errors.map((e) =>
+    el("tr", {},
+      el("td", {}, new Date(e.time).toLocaleTimeString()),
+      el("td", { class: "num" }, String(e.status)),
+      el("td", {}, e.error),
+      el("td", {}, e.model),
+      el("td", {}, e.provider === "none" ? "–" : `${e.provider}:${e.account}`),
+      el("td", { class: "message", title: `${e.message}\nrequest ${e.request_id}` }, e.message),
+    ));
+  $("errors").replaceChildren(...(rows.length ? rows : [el("tr", {}, el("td", { colspan: 6, class: "empty" }, "No errors"))]));
+}
+
+async function refresh() {
+  try {
+    const [accountList, stats] = await Promise.all([api("/accounts"), api("/stats")]);
+    accounts = accountList.accounts;
+    renderAccounts();
+    renderRates(stats.requests_per_minute);
+    renderModels(stats.models);
+    renderErrors(stats.recent_errors);
+    $("updated").textContent = "Updated " + new Date().toLocaleTimeString();
+  } catch (e) {
+    if (e.status === 401 || e.status === 404) {
+      showLogin(e.message);
+    } else {
+      $("updated").textContent = "Update failed: " + e.message;
+    }
+  }
+}
+
+function showLogin(message = "") {
+  clearInterval(pollTimer);
+  sessionStorage.removeItem(TOKEN_KEY);
+  $("main").hidden = true;
+  $("logout").hidden = true;
+  $("login").hidden = false;
+  $("login-error").textContent = message;
+  $("token").focus();
+}
+
+function start() {
+  $("login").hidden = true;
+  $("main").hidden = false;
+  $("logout").hidden = false;
+  refresh();
+  pollTimer = setInterval(refresh, POLL_MS);
+}
+
+$("login").addEventListener("submit", (event) => {
+  event.preventDefault();
+  sessionStorage.setItem(TOKEN_KEY, $("token").value);
+  $("token").value = "";
+  start();
+});
+$("logout").addEventListener("click", () => showLogin());
+setInterval(tickCountdowns, 1000);
+
+if (token()) start(); else showLogin();
@@ -0,0 +1,47 @@
+use axum::{
+    http::header,
+    response::{Html, IntoResponse, Redirect},
+    routing::get,
+    Router,
+};
+
+use super::router::AppState;
+
+// Compiled into the binary, so the dashboard needs nothing installed next to it
+const INDEX_HTML: &str = include_str!("index.html");
+const APP_JS: &str = include_str!("app.js");
+const STYLE_CSS: &str = include_str!("style.css");
+
+/// The dashboard page and its assets under `/dashboard`. The page itself is
+/// public; its data comes from the admin API, using the token entered in it.
+pub(super) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/dashboard", get(index))
+        .route("/dashboard/", get(|| async { Redirect::permanent("/dashboard") }))
+        .route("/dashboard/app.js", get(app_js))
+        .route("/dashboard/style.css", get(style_css))
+}
+
+async fn index() -> impl IntoResponse {
+    ([(header::CACHE_CONTROL, "no-cache")], Html(INDEX_HTML))
+}
+
+async fn app_js() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/javascript; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        APP_JS,
+    )
+}
+
+async fn style_css() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/css; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        STYLE_CSS,
+    )
+}
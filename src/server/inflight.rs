@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::metrics::RequestLabels;
+
+/// Chat completions currently being served, for the admin API
+#[derive(Default)]
+pub struct InFlight {
+    next_key: AtomicU64,
+    requests: Mutex<HashMap<u64, InFlightRequest>>,
+}
+
+/// A request from the moment it arrives until its response has been streamed
+#[derive(Debug, Clone, Serialize)]
+pub struct InFlightRequest {
+    pub request_id: String,
+    pub client: String,
+    pub provider: String,
+    pub model: String,
+    pub account: String,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Removes its request from the list when dropped
+pub struct InFlightGuard {
+    in_flight: Arc<InFlight>,
+    key: u64,
+}
+
+impl InFlight {
+    pub fn start(self: &Arc<Self>, request_id: &str, labels: &RequestLabels) -> InFlightGuard {
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        let request = InFlightRequest {
+            request_id: request_id.to_string(),
+            client: labels.client.clone(),
+            provider: labels.provider.clone(),
+            model: labels.model.clone(),
+            account: labels.account.clone(),
+            started_at: Utc::now(),
+        };
+        self.requests.lock().unwrap().insert(key, request);
+
+        InFlightGuard {
+            in_flight: Arc::clone(self),
+            key,
+        }
+    }
+
+    /// All in-flight requests, oldest first
+    pub fn list(&self) -> Vec<InFlightRequest> {
+        let mut requests: Vec<_> = self.requests.lock().unwrap().values().cloned().collect();
+        requests.sort_by_key(|r| r.started_at);
+        requests
+    }
+
+    /// In-flight requests on an account
+    pub fn count(&self, provider: &str, account: &str) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|r| r.provider == provider && r.account == account)
+            .count()
+    }
+}
+
+impl InFlightGuard {
+    /// Record where the request was routed
+    pub fn update(&self, labels: &RequestLabels) {
+        if let Some(request) = self.in_flight.requests.lock().unwrap().get_mut(&self.key) {
+            request.provider = labels.provider.clone();
+            request.model = labels.model.clone();
+            request.account = labels.account.clone();
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.requests.lock().unwrap().remove(&self.key);
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::{Arc, RwLock};
+
+use crate::config::Config;
+use crate::http::HttpClients;
+
+/// The running server's config and the HTTP clients built from it, replaced
+/// together when config.toml is reloaded. Requests take a snapshot when they
+/// start, so a reload never changes settings halfway through one.
+#[derive(Clone)]
+pub struct LiveConfig {
+    inner: Arc<RwLock<(Arc<Config>, HttpClients)>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: Config) -> anyhow::Result<Self> {
+        let clients = HttpClients::new(&config.http)?;
+        Ok(Self {
+            inner: Arc::new(RwLock::new((Arc::new(config), clients))),
+        })
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.inner.read().unwrap().0)
+    }
+
+    pub fn clients(&self) -> HttpClients {
+        self.inner.read().unwrap().1.clone()
+    }
+
+    /// Re-read config.toml. The current config is kept when the new one
+    /// doesn't parse. `[server]`, `[logging]` and `[telemetry]` only take
+    /// effect on restart.
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        let config = Config::load().await?;
+        let clients = HttpClients::new(&config.http)?;
+
+        *self.inner.write().unwrap() = (Arc::new(config), clients);
+        tracing::info!("Reloaded config from {}", Config::path()?.display());
+        Ok(())
+    }
+}
@@ -1,12 +1,29 @@
+mod admin;
+mod dashboard;
+mod inflight;
+mod live;
+mod observe;
+mod reload;
 mod router;
+mod stats;
+
+pub use live::LiveConfig;
+pub use observe::Completion;
 
 use std::sync::Arc;
 
 use axum::Router;
 use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+use tracing::Instrument;
 
-use crate::accounts::AccountManager;
+use crate::accounts::{Account, AccountManager};
+use crate::auth;
+use crate::capture;
 use crate::config::Config;
+use crate::http::HttpClients;
+use crate::metrics::Metrics;
+use crate::providers::ModelRegistry;
 
 pub struct Server {
     listener: TcpListener,
@@ -16,13 +33,26 @@ pub struct Server {
 impl Server {
     pub async fn new(host: &str, port: u16) -> anyhow::Result<Self> {
         let config = Config::load().await?;
-        let account_manager = Arc::new(AccountManager::load().await?);
+        let account_manager = Arc::new(AccountManager::load(&config.credentials).await?);
 
         if account_manager.is_empty().await {
             anyhow::bail!("No accounts configured. Use 'omniproxy account add <provider>' first.");
         }
 
-        let router = router::create_router(account_manager, config);
+        let live = LiveConfig::new(config)?;
+
+        let registry = Arc::new(RwLock::new(ModelRegistry::load()?));
+        tokio::spawn(refresh_registry(
+            Arc::clone(&registry),
+            Arc::clone(&account_manager),
+            live.clone(),
+        ));
+
+        tokio::spawn(reload::watch(Arc::clone(&account_manager), live.clone()));
+        tokio::spawn(prune_captures(live.clone()));
+
+        let metrics = Arc::new(Metrics::new()?);
+        let router = router::create_router(account_manager, live, registry, metrics);
 
         let addr = format!("{}:{}", host, port);
         let listener = TcpListener::bind(&addr).await?;
@@ -31,7 +61,67 @@ impl Server {
     }
 
     pub async fn run(self) -> anyhow::Result<()> {
-        axum::serve(self.listener, self.router).await?;
+        axum::serve(self.listener, self.router)
+            .with_graceful_shutdown(async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await?;
         Ok(())
     }
 }
+
+/// Keep the model registry fresh, re-fetching whenever it outlives its TTL
+async fn refresh_registry(registry: Arc<RwLock<ModelRegistry>>, account_manager: Arc<AccountManager>, live: LiveConfig) {
+    loop {
+        let config = live.config();
+        let current = registry.read().await.clone();
+        if current.is_stale(&config) {
+            match current.refresh(&account_manager, &live.clients()).await {
+                Ok(refreshed) => *registry.write().await = refreshed,
+                Err(e) => tracing::warn!("Failed to refresh model registry: {}", e),
+            }
+        }
+
+        // Re-check at least hourly so a shortened TTL takes effect
+        let interval = config.registry.ttl_secs.clamp(60, 3600);
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+    }
+}
+
+/// Remove old request captures once a minute, off the request path
+async fn prune_captures(live: LiveConfig) {
+    loop {
+        let config = live.config();
+        if config.capture.enabled {
+            let pruned = tokio::task::spawn_blocking(move || capture::prune(&config)).await;
+            if let Err(e) = pruned.map_err(anyhow::Error::from).and_then(|r| r) {
+                tracing::warn!("Failed to prune captures: {}", e);
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    }
+}
+
+/// Refresh one account's OAuth token and store it in memory; the caller saves
+async fn refresh_account(account_manager: &AccountManager, clients: &HttpClients, account: &Account) -> anyhow::Result<()> {
+    let current = &account.credentials;
+    if current.refresh_token.is_empty() {
+        anyhow::bail!("No refresh token");
+    }
+
+    let client = clients.for_account(account)?;
+    let span = tracing::info_span!("refresh_token", provider = account.provider.as_str(), account = account.name.as_str());
+    let mut credentials = auth::refresh_token(&account.provider, &client, &current.refresh_token)
+        .instrument(span)
+        .await?;
+
+    credentials.account_id = credentials.account_id.or_else(|| current.account_id.clone());
+    credentials.email = credentials.email.or_else(|| current.email.clone());
+
+    account_manager
+        .update_credentials(&account.provider, &account.name, credentials)
+        .await?;
+    tracing::info!("Refreshed token for {}:{}", account.provider, account.name);
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, Bytes};
+use futures::StreamExt;
+use serde_json::Value;
+
+/// What happened while a response body was sent to the client
+#[derive(Debug)]
+pub struct Completion {
+    pub time_to_first_token: Option<Duration>,
+    pub duration: Duration,
+    /// OpenAI `usage` from the response, or from the last stream chunk carrying it
+    pub usage: Option<Value>,
+}
+
+impl Completion {
+    pub fn prompt_tokens(&self) -> Option<u64> {
+        self.usage.as_ref()?.get("prompt_tokens")?.as_u64()
+    }
+
+    pub fn completion_tokens(&self) -> Option<u64> {
+        self.usage.as_ref()?.get("completion_tokens")?.as_u64()
+    }
+}
+
+/// Wrap a response body to call `on_finish` once it has been sent, or the
+/// client has gone away. `streaming` is whether the body is an SSE stream
+/// rather than one JSON document.
+pub fn observe_body(
+    body: Body,
+    streaming: bool,
+    started: Instant,
+    on_finish: impl FnOnce(Completion) + Send + 'static,
+) -> Body {
+    let mut observer = BodyObserver {
+        streaming,
+        started,
+        first_byte: None,
+        buffer: Vec::new(),
+        usage: None,
+        on_finish: Some(Box::new(on_finish)),
+    };
+
+    let stream = body.into_data_stream().map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            observer.chunk(bytes);
+        }
+        chunk
+    });
+
+    Body::from_stream(stream)
+}
+
+/// Watches response bytes go by; reports when dropped, after the last chunk
+/// or when the client disconnects
+struct BodyObserver {
+    streaming: bool,
+    started: Instant,
+    first_byte: Option<Instant>,
+    buffer: Vec<u8>,
+    usage: Option<Value>,
+    on_finish: Option<Box<dyn FnOnce(Completion) + Send>>,
+}
+
+impl BodyObserver {
+    fn chunk(&mut self, bytes: &Bytes) {
+        if self.first_byte.is_none() && !bytes.is_empty() {
+            self.first_byte = Some(Instant::now());
+        }
+        self.buffer.extend_from_slice(bytes);
+        if !self.streaming {
+            return;
+        }
+
+        // Streams: pick `usage` out of complete SSE lines and keep only the partial tail
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            if let Some(data) = line.strip_prefix(b"data: ") {
+                if let Some(usage) = usage_of(data) {
+                    self.usage = Some(usage);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for BodyObserver {
+    fn drop(&mut self) {
+        // A non-streaming response is parsed whole, as it may be pretty-printed
+        let usage = self.usage.take().or_else(|| usage_of(&self.buffer));
+
+        if let Some(on_finish) = self.on_finish.take() {
+            on_finish(Completion {
+                time_to_first_token: self.first_byte.map(|t| t - self.started),
+                duration: self.started.elapsed(),
+                usage,
+            });
+        }
+    }
+}
+
+/// The `usage` object of an OpenAI response or stream chunk
+fn usage_of(json: &[u8]) -> Option<Value> {
+    let value: Value = serde_json::from_slice(json.trim_ascii()).ok()?;
+    value.get("usage").filter(|u| u.is_object()).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use http_body_util::BodyExt;
+
+    use super::*;
+
+    /// Send `chunks` through an observer and return what it reported
+    async fn observe(chunks: &[&'static str], streaming: bool) -> Completion {
+        let completion = Arc::new(Mutex::new(None));
+        let chunks: Vec<_> = chunks.iter().map(|c| Ok::<_, std::io::Error>(Bytes::from_static(c.as_bytes()))).collect();
+        let body = Body::from_stream(futures::stream::iter(chunks));
+
+        let reported = Arc::clone(&completion);
+        let body = observe_body(body, streaming, Instant::now(), move |c| {
+            *reported.lock().unwrap() = Some(c);
+        });
+        body.collect().await.unwrap();
+
+        let completion = completion.lock().unwrap().take();
+        completion.expect("observer reported nothing")
+    }
+
+    #[tokio::test]
+    async fn usage_from_pretty_printed_json() {
+        let completion = observe(
+            &["{\n  \"id\": \"1\",\n  \"usage\": {\n", "    \"prompt_tokens\": 12,\n    \"completion_tokens\": 34\n  }\n}\n"],
+            false,
+        )
+        .await;
+
+        assert_eq!(completion.prompt_tokens(), Some(12));
+        assert_eq!(completion.completion_tokens(), Some(34));
+    }
+
+    #[tokio::test]
+    async fn usage_from_last_stream_chunk_split_across_reads() {
+        let completion = observe(
+            &[
+                "data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\n",
+                "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,",
+                "\"completion_tokens\":7}}\n\ndata: [DONE]\n\n",
+            ],
+            true,
+        )
+        .await;
+
+        assert_eq!(completion.prompt_tokens(), Some(5));
+        assert_eq!(completion.completion_tokens(), Some(7));
+        assert!(completion.time_to_first_token.is_some());
+    }
+
+    #[tokio::test]
+    async fn no_usage_without_usage_object() {
+        let completion = observe(&["data: {\"choices\":[]}\n\ndata: [DONE]\n\n"], true).await;
+
+        assert!(completion.usage.is_none());
+    }
+}
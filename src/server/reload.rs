@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::accounts::AccountManager;
+use crate::config::Config;
+
+use super::LiveConfig;
+
+/// Wait this long after a change for more, so an editor's save or a CLI
+/// write is reloaded once, complete
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Reload config.toml and accounts.json when they change on disk or the
+/// process receives SIGHUP
+pub(super) async fn watch(account_manager: Arc<AccountManager>, live: LiveConfig) {
+    let (config_path, accounts_path) = match (Config::path(), Config::accounts_path()) {
+        (Ok(config), Ok(accounts)) => (config, accounts),
+        _ => {
+            tracing::warn!("Could not find the config directory; hot reload is disabled");
+            return;
+        }
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+
+    // Dropping the watcher stops it, so it lives as long as this task
+    let _watcher = match watch_dir(&config_path, tx.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            tracing::warn!("Failed to watch config files, reload with SIGHUP instead: {}", e);
+            None
+        }
+    };
+
+    #[cfg(unix)]
+    if let Err(e) = forward_hangup(tx, [config_path.clone(), accounts_path.clone()]) {
+        tracing::warn!("Failed to listen for SIGHUP: {}", e);
+    }
+
+    while let Some(path) = rx.recv().await {
+        tokio::time::sleep(DEBOUNCE).await;
+
+        let mut changed = vec![path];
+        while let Ok(path) = rx.try_recv() {
+            changed.push(path);
+        }
+
+        let config = changed.iter().any(|p| same_file(p, &config_path));
+        let accounts = changed.iter().any(|p| same_file(p, &accounts_path));
+
+        // A bad edit is logged and the running settings are kept
+        if let Err(e) = reload(&account_manager, &live, config, accounts).await {
+            tracing::warn!("{}", e);
+        }
+    }
+}
+
+/// Reload the selected files. Requests already in flight keep the settings and
+/// account they started with.
+pub(super) async fn reload(account_manager: &AccountManager, live: &LiveConfig, config: bool, accounts: bool) -> anyhow::Result<()> {
+    if config {
+        live.reload()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to reload config.toml: {}", e))?;
+    }
+
+    if accounts {
+        let kept_newer = account_manager
+            .reload()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to reload accounts.json: {}", e))?;
+        tracing::info!("Reloaded accounts from {}", Config::accounts_path()?.display());
+
+        // The file was written with credentials older than ones refreshed
+        // since; write the newer ones back
+        if kept_newer {
+            account_manager.save().await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Watch the directory rather than the files, since editors and atomic
+/// writes replace a file instead of modifying it
+fn watch_dir(config_path: &Path, tx: mpsc::UnboundedSender<PathBuf>) -> anyhow::Result<notify::RecommendedWatcher> {
+    let dir = config_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Config path has no parent directory"))?;
+    std::fs::create_dir_all(dir)?;
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+            for path in event.paths {
+                let _ = tx.send(path);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("Config watcher error: {}", e),
+    })?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    Ok(watcher)
+}
+
+#[cfg(unix)]
+fn forward_hangup(tx: mpsc::UnboundedSender<PathBuf>, paths: [PathBuf; 2]) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            tracing::info!("Received SIGHUP, reloading");
+            for path in &paths {
+                let _ = tx.send(path.clone());
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn same_file(changed: &Path, watched: &Path) -> bool {
+    changed.file_name().is_some() && changed.file_name() == watched.file_name()
+}
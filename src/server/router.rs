@@ -1,30 +1,64 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use axum::{
     body::Body,
-    extract::State,
-    http::{Request, Response, StatusCode},
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Uri},
+    response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
 use http_body_util::BodyExt;
 use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use tower::ServiceBuilder;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::field::Empty;
+use tracing::{Instrument, Level};
 
 use crate::accounts::{AccountManager, Provider};
+use crate::capture::{self, Capture};
 use crate::config::Config;
-use crate::providers;
+use crate::metrics::{self, Metrics, RequestLabels};
+use crate::providers::{self, ModelRegistry, ProxyError};
+
+use super::inflight::{InFlight, InFlightGuard};
+use super::stats::{RecentError, Stats};
+use super::{admin, dashboard, observe, LiveConfig};
+
+/// Response header naming the upstream model that served the request
+const SERVED_MODEL_HEADER: &str = "x-omniproxy-model";
+
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Response header naming the debug capture of the request, when capture is on
+const CAPTURE_ID_HEADER: &str = "x-omniproxy-capture-id";
 
 #[derive(Clone)]
-struct AppState {
-    account_manager: Arc<AccountManager>,
-    #[allow(dead_code)]
-    config: Config,
+pub(super) struct AppState {
+    pub(super) account_manager: Arc<AccountManager>,
+    pub(super) live: LiveConfig,
+    pub(super) registry: Arc<RwLock<ModelRegistry>>,
+    pub(super) metrics: Arc<Metrics>,
+    pub(super) in_flight: Arc<InFlight>,
+    pub(super) stats: Arc<Stats>,
 }
 
-pub fn create_router(account_manager: Arc<AccountManager>, config: Config) -> Router {
+pub fn create_router(
+    account_manager: Arc<AccountManager>,
+    live: LiveConfig,
+    registry: Arc<RwLock<ModelRegistry>>,
+    metrics: Arc<Metrics>,
+) -> Router {
     let state = AppState {
         account_manager,
-        config,
+        live,
+        registry,
+        metrics,
+        in_flight: Arc::new(InFlight::default()),
+        stats: Arc::new(Stats::default()),
     };
 
     Router::new()
@@ -32,47 +66,84 @@ pub fn create_router(account_manager: Arc<AccountManager>, config: Config) -> Ro
         .route("/chat/completions", post(chat_completions))
         .route("/v1/models", get(list_models))
         .route("/models", get(list_models))
+        .route("/v1/models/{*id}", get(get_model))
+        .route("/models/{*id}", get(get_model))
         .route("/health", get(health))
+        .route("/metrics", get(get_metrics))
+        .nest("/admin", admin::router(state.clone()))
+        .merge(dashboard::router())
+        .fallback(not_found)
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(make_span)
+                        .on_response(DefaultOnResponse::new().level(Level::INFO)),
+                )
+                .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER)),
+        )
         .with_state(state)
 }
 
+/// Span for each request. Only the path is recorded, since query strings and
+/// headers can carry API keys.
+fn make_span(request: &Request<Body>) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        request_id = request_id(request.headers()),
+    )
+}
+
+/// The request's ID, accepted from the client or assigned by `SetRequestIdLayer`
+fn request_id(headers: &HeaderMap) -> &str {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
+
+async fn not_found(uri: Uri) -> (StatusCode, Json<Value>) {
+    error(StatusCode::NOT_FOUND, format!("Unknown endpoint: {}", uri.path()))
+}
+
 async fn health() -> Json<Value> {
     Json(json!({ "status": "ok" }))
 }
 
-async fn list_models(State(state): State<AppState>) -> Json<Value> {
-    let mut models = Vec::new();
-
-    // Add models based on available accounts
-    if state.account_manager.count(&Provider::Codex).await > 0 {
-        models.extend([
-            "gpt-4o", "gpt-4o-mini", "gpt-4-turbo", "gpt-4",
-            "o1", "o1-mini", "o1-preview", "o3-mini",
-        ]);
-    }
+async fn get_metrics(State(state): State<AppState>) -> Result<Response<Body>, (StatusCode, Json<Value>)> {
+    let text = state
+        .metrics
+        .render(&state.account_manager)
+        .await
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    if state.account_manager.count(&Provider::Claude).await > 0 {
-        models.extend([
-            "claude-sonnet-4-20250514", "claude-opus-4-20250514",
-            "claude-3-5-sonnet-20241022", "claude-3-5-haiku-20241022",
-            "claude-3-opus-20240229",
-        ]);
-    }
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(text))
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
 
-    if state.account_manager.count(&Provider::Gemini).await > 0 {
-        models.extend([
-            "gemini-2.0-flash", "gemini-2.0-flash-thinking",
-            "gemini-1.5-pro", "gemini-1.5-flash",
-        ]);
+async fn list_models(State(state): State<AppState>) -> Json<Value> {
+    // Only list models for providers with configured accounts
+    let mut available = Vec::new();
+    for provider in [Provider::Codex, Provider::Claude, Provider::Gemini] {
+        if state.account_manager.count(&provider).await > 0 {
+            available.push(provider);
+        }
     }
 
-    let data: Vec<Value> = models
+    let registry = state.registry.read().await;
+    let data: Vec<Value> = registry
+        .list(&state.live.config(), |p| available.contains(p))
         .iter()
         .map(|m| {
             json!({
-                "id": m,
+                "id": m.name,
                 "object": "model",
-                "owned_by": "omniproxy",
+                "owned_by": m.provider.as_str(),
             })
         })
         .collect();
@@ -83,88 +154,531 @@ async fn list_models(State(state): State<AppState>) -> Json<Value> {
     }))
 }
 
-async fn chat_completions(
+async fn get_model(
     State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let target = providers::resolve_model(&state.live.config(), &id)
+        .map_err(|_| error(StatusCode::NOT_FOUND, format!("Model not found: {}", id)))?;
+
+    let registry = state.registry.read().await;
+    let mut model = json!({
+        "id": id,
+        "object": "model",
+        "owned_by": target.provider.as_str(),
+    });
+
+    if let Some(info) = registry.find(&target.provider, &target.model) {
+        model["upstream_model"] = json!(info.name);
+        model["context_length"] = json!(info.context_length);
+        model["max_output_tokens"] = json!(info.max_output_tokens);
+        model["capabilities"] = json!(info.capabilities);
+        model["reasoning_levels"] = json!(info.reasoning_levels);
+        model["pricing"] = json!(info.pricing);
+    }
+
+    Ok(Json(model))
+}
+
+async fn chat_completions(State(state): State<AppState>, request: Request<Body>) -> Response<Body> {
+    let started = Instant::now();
+    let config = state.live.config();
+    let request_id = request_id(request.headers()).to_string();
+    let mut labels = RequestLabels {
+        provider: "none".to_string(),
+        model: metrics::UNKNOWN_MODEL.to_string(),
+        account: "none".to_string(),
+        client: metrics::client_key(request.headers()),
+    };
+    let in_flight = state.in_flight.start(&request_id, &labels);
+
+    // Covers routing, the upstream call and streaming the response back
+    let span = tracing::info_span!(
+        "chat",
+        gen_ai.operation.name = "chat",
+        gen_ai.request.model = Empty,
+        gen_ai.response.model = Empty,
+        gen_ai.provider.name = Empty,
+        gen_ai.usage.input_tokens = Empty,
+        gen_ai.usage.output_tokens = Empty,
+        omniproxy.account = Empty,
+        error.type = Empty,
+        otel.status_code = Empty,
+    );
+
+    let capture = if config.capture.enabled {
+        Capture::start(&config.capture)
+            .await
+            .inspect_err(|e| tracing::warn!("Failed to start capture: {}", e))
+            .ok()
+    } else {
+        None
+    };
+
+    let result = route_chat_completion(&state, &config, request, &mut labels, capture.as_ref(), &in_flight)
+        .instrument(span.clone())
+        .await;
+
+    let mut response = match result {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            state.metrics.record_request(&labels, status);
+            state.stats.record_request(&labels, status >= 400);
+
+            let metrics = Arc::clone(&state.metrics);
+            let stats = Arc::clone(&state.stats);
+            let account_manager = Arc::clone(&state.account_manager);
+            let (parts, mut body) = response.into_parts();
+            if let Some(capture) = &capture {
+                body = capture.tee(&capture::response_stage(capture::RESPONSE, &parts.headers), body);
+            }
+            let streaming = providers::is_event_stream(&parts.headers);
+            // The access log names the model that served the request, even one the metrics label as unknown
+            let served_model = parts
+                .headers
+                .get(SERVED_MODEL_HEADER)
+                .and_then(|m| m.to_str().ok())
+                .unwrap_or(&labels.model)
+                .to_string();
+            let body = observe::observe_body(body, streaming, started, move |completion| {
+                // Still in flight until the response has been streamed
+                drop(in_flight);
+                metrics.record_completion(&labels, &completion);
+                stats.record_tokens(
+                    &labels,
+                    completion.prompt_tokens().unwrap_or(0),
+                    completion.completion_tokens().unwrap_or(0),
+                );
+                if let Ok(provider) = Provider::from_str(&labels.provider) {
+                    account_manager.record_tokens(
+                        &provider,
+                        &labels.account,
+                        completion.prompt_tokens().unwrap_or(0),
+                        completion.completion_tokens().unwrap_or(0),
+                    );
+                }
+                // Unsigned values would reach OpenTelemetry as strings
+                span.record("gen_ai.usage.input_tokens", completion.prompt_tokens().map(|t| t as i64));
+                span.record("gen_ai.usage.output_tokens", completion.completion_tokens().map(|t| t as i64));
+                tracing::info!(
+                    target: "omniproxy::access",
+                    request_id,
+                    status,
+                    provider = labels.provider,
+                    account = labels.account,
+                    model = served_model,
+                    client = labels.client,
+                    ttft_ms = completion.time_to_first_token.map(|t| t.as_millis() as u64),
+                    latency_ms = completion.duration.as_millis() as u64,
+                    prompt_tokens = completion.prompt_tokens(),
+                    completion_tokens = completion.completion_tokens(),
+                    "chat completion"
+                );
+            });
+            Response::from_parts(parts, body)
+        }
+        Err(e) => {
+            state.metrics.record_request(&labels, e.status().as_u16());
+            state.stats.record_request(&labels, true);
+            state.stats.record_error(RecentError {
+                time: chrono::Utc::now(),
+                request_id: request_id.clone(),
+                status: e.status().as_u16(),
+                error: e.label().to_string(),
+                message: e.to_string(),
+                provider: labels.provider.clone(),
+                model: labels.model.clone(),
+                account: labels.account.clone(),
+            });
+            span.record("error.type", e.label());
+            span.record("otel.status_code", "ERROR");
+            tracing::info!(
+                target: "omniproxy::access",
+                request_id,
+                status = e.status().as_u16(),
+                provider = labels.provider,
+                account = labels.account,
+                model = labels.model,
+                client = labels.client,
+                latency_ms = started.elapsed().as_millis() as u64,
+                error = e.label(),
+                "chat completion failed"
+            );
+            let response = e.into_response();
+            if let Some(capture) = &capture {
+                let (parts, body) = response.into_parts();
+                let body = capture.tee(&capture::response_stage(capture::RESPONSE, &parts.headers), body);
+                Response::from_parts(parts, body)
+            } else {
+                response
+            }
+        }
+    };
+
+    if let Some(value) = capture.and_then(|c| HeaderValue::from_str(c.id()).ok()) {
+        response.headers_mut().insert(CAPTURE_ID_HEADER, value);
+    }
+    response
+}
+
+/// Route a chat completion to the first candidate model and account that can
+/// serve it, filling in `labels` with what was chosen
+async fn route_chat_completion(
+    state: &AppState,
+    config: &Config,
     request: Request<Body>,
-) -> Result<Response<Body>, (StatusCode, Json<Value>)> {
+    labels: &mut RequestLabels,
+    capture: Option<&Capture>,
+    in_flight: &InFlightGuard,
+) -> Result<Response<Body>, ProxyError> {
+    let clients = state.live.clients();
+
     // Read body to extract model
-    let (parts, body) = request.into_parts();
-    let body_bytes = body
-        .collect()
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "error": format!("Failed to read body: {}", e) })),
-            )
-        })?
-        .to_bytes();
-
-    let body_json: Value = serde_json::from_slice(&body_bytes).map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(json!({ "error": format!("Invalid JSON: {}", e) })),
-        )
-    })?;
+    let (mut parts, body) = request.into_parts();
+    let body_bytes = body.collect().await?.to_bytes();
+
+    // Providers record the upstream stages through the request extensions
+    if let Some(capture) = capture {
+        let uri = parts.uri.to_string();
+        capture.write_request(capture::INBOUND_REQUEST, parts.method.as_str(), &uri, Some(&parts.headers), &body_bytes);
+        parts.extensions.insert(capture.clone());
+    }
+
+    if !config.logging.redact {
+        tracing::debug!(headers = ?parts.headers, body = %String::from_utf8_lossy(&body_bytes), "Request");
+    }
+
+    let body_json: Value = serde_json::from_slice(&body_bytes)
+        .map_err(|e| ProxyError::InvalidRequest(format!("Invalid JSON: {}", e)))?;
 
     let model = body_json
         .get("model")
         .and_then(|m| m.as_str())
-        .ok_or_else(|| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(json!({ "error": "Missing 'model' field" })),
-            )
-        })?;
-
-    // Determine provider from model
-    let provider = determine_provider(model).ok_or_else(|| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(json!({ "error": format!("Unknown model: {}", model) })),
-        )
-    })?;
+        .ok_or_else(|| ProxyError::InvalidRequest("Missing 'model' field".into()))?
+        .to_string();
+    // Metrics only name models the registry knows, so made-up model names
+    // can't add series
+    tracing::Span::current().record("gen_ai.request.model", model.as_str());
 
-    // Get next account using round-robin
-    let account = state
-        .account_manager
-        .next_account(&provider)
-        .await
-        .ok_or_else(|| {
-            (
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(json!({ "error": format!("No valid accounts for provider: {}", provider) })),
-            )
-        })?;
-
-    tracing::info!(
-        "Routing request for model '{}' to {} account '{}'",
-        model,
-        provider,
-        account.name
-    );
+    // The requested model first, then its configured fallbacks
+    let fallbacks = config.fallbacks.get(&model).cloned().unwrap_or_default();
+    let candidates: Vec<String> = std::iter::once(model.clone()).chain(fallbacks).collect();
+    let mut unavailable = Vec::new();
 
-    // Reconstruct request
-    let request = Request::from_parts(parts, Body::from(body_bytes.to_vec()));
+    for (i, candidate) in candidates.iter().enumerate() {
+        let is_last = i + 1 == candidates.len();
 
-    // Proxy to provider
-    providers::proxy_request(&account, request).await.map_err(|e| {
-        tracing::error!("Proxy error: {}", e);
-        (
-            StatusCode::BAD_GATEWAY,
-            Json(json!({ "error": format!("Proxy error: {}", e) })),
-        )
-    })
+        let routing = tracing::info_span!(
+            "route_model",
+            candidate = %candidate,
+            gen_ai.provider.name = Empty,
+            gen_ai.request.model = Empty,
+        );
+
+        // Determine provider and upstream model id
+        let mut target = match routing.in_scope(|| providers::resolve_model(config, candidate)) {
+            Ok(target) => target,
+            Err(e) if i == 0 => return Err(ProxyError::InvalidRequest(e.to_string())),
+            Err(e) => {
+                tracing::warn!("Skipping fallback '{}': {}", candidate, e);
+                unavailable.push(e.to_string());
+                continue;
+            }
+        };
+        let provider = target.provider;
+        routing.record("gen_ai.provider.name", genai_provider(&provider));
+
+        // Each candidate gets its own copy so suffix-derived settings don't leak into fallbacks
+        let mut request_json = body_json.clone();
+        let mut metric_model = metrics::UNKNOWN_MODEL.to_string();
+
+        // Reject requests the model or provider can't serve before spending an upstream call
+        let rejected = {
+            let registry = state.registry.read().await;
+            let _entered = routing.enter();
+
+            // `o3-high` / `claude-sonnet-4:thinking` select a reasoning effort
+            let (base, effort) = providers::split_effort_suffix(&registry, &provider, &target.model);
+            target.model = base;
+            if let Some(effort) = effort {
+                request_json["reasoning_effort"] = Value::String(effort);
+            }
+
+            let info = registry.find(&provider, &target.model);
+            if let Some(info) = info {
+                metric_model = info.name.clone();
+            }
+            let limits = info.map(|info| info.check_request(&request_json));
+
+            routing.record("gen_ai.request.model", target.model.as_str());
+
+            match providers::check_supported(&provider, &request_json, config.translation.strict) {
+                Ok(()) => limits,
+                Err(e) => Some(Err(e)),
+            }
+        };
+        drop(routing);
+        if let Some(Err(e)) = rejected {
+            if is_last {
+                return Err(e);
+            }
+            tracing::warn!("Skipping model '{}': {}", candidate, e);
+            state.metrics.record_failover(&provider, &metric_model, e.label());
+            unavailable.push(e.to_string());
+            continue;
+        }
+
+        // Use the pinned account, or the next one in round-robin order
+        let selection = tracing::info_span!(
+            "select_account",
+            gen_ai.provider.name = genai_provider(&provider),
+            omniproxy.account = Empty,
+        );
+        let account = async {
+            match &target.account {
+                Some(name) => {
+                    let account = state.account_manager.get(&provider, name).await.ok_or_else(|| {
+                        ProxyError::InvalidRequest(format!("Account not found: {}:{}", provider, name))
+                    })?;
+                    let available = state.account_manager.is_available(&account);
+                    Ok::<_, ProxyError>(available.then_some(account))
+                }
+                None => Ok(state.account_manager.next_account(&provider).await),
+            }
+        }
+        .instrument(selection.clone())
+        .await?;
+        if let Some(account) = &account {
+            selection.record("omniproxy.account", account.name.as_str());
+        }
+
+        let Some(account) = account else {
+            tracing::warn!("No available {} account for model '{}'", provider, candidate);
+            state.metrics.record_failover(&provider, &metric_model, "no_account");
+            unavailable.push(format!("No valid accounts for provider: {}", provider));
+            continue;
+        };
+
+        tracing::info!(
+            "Routing request for model '{}' ({}) to {} account '{}'",
+            candidate,
+            target.model,
+            provider,
+            account.name
+        );
+
+        labels.provider = provider.as_str().to_string();
+        labels.model = metric_model.clone();
+        labels.account = account.name.clone();
+        in_flight.update(labels);
+
+        let chat = tracing::Span::current();
+        chat.record("gen_ai.provider.name", genai_provider(&provider));
+        chat.record("omniproxy.account", account.name.as_str());
+
+        // Reconstruct request with the upstream model id
+        request_json["model"] = Value::String(target.model.clone());
+        let body_bytes = serde_json::to_vec(&request_json)
+            .map_err(|e| ProxyError::Internal(format!("Failed to encode request: {}", e)))?;
+        let request = Request::from_parts(parts.clone(), Body::from(body_bytes));
+
+        // Proxy to provider; each attempt is its own span, so fallbacks show up as siblings
+        let upstream = tracing::info_span!(
+            "upstream",
+            otel.name = format!("chat {}", target.model),
+            otel.kind = "client",
+            gen_ai.operation.name = "chat",
+            gen_ai.provider.name = genai_provider(&provider),
+            gen_ai.request.model = target.model.as_str(),
+            omniproxy.account = account.name.as_str(),
+            http.response.status_code = Empty,
+            error.type = Empty,
+            otel.status_code = Empty,
+        );
+        let client = clients.for_account(&account).map_err(|e| ProxyError::Internal(e.to_string()))?;
+        let result = providers::proxy_request(&client, &account, request, config)
+            .instrument(upstream.clone())
+            .await;
+
+        let mut response = match result {
+            Ok(response) => {
+                chat.record("gen_ai.response.model", target.model.as_str());
+                state.account_manager.record_request(&account, None);
+                state.metrics.record_upstream(&provider, &account.name, response.status().as_str());
+                upstream.record("http.response.status_code", i64::from(response.status().as_u16()));
+                response
+            }
+            Err(e) => {
+                if let Some(status) = e.upstream().map(|u| u.status) {
+                    upstream.record("http.response.status_code", i64::from(status));
+                }
+                if let (Some(capture), Some(error)) = (capture, e.upstream()) {
+                    capture.write_json(&format!("{}.json", capture::UPSTREAM_RESPONSE), &error.body);
+                }
+                upstream.record("error.type", e.label());
+                upstream.record("otel.status_code", "ERROR");
+                state.account_manager.record_request(&account, Some(e.to_string()));
+
+                match e.upstream() {
+                    Some(upstream) => state.metrics.record_upstream(&provider, &account.name, &upstream.status.to_string()),
+                    None => state.metrics.record_upstream(&provider, &account.name, e.label()),
+                }
+
+                // Rate-limited accounts wait out Retry-After; rejected credentials
+                // sit out the default cooldown
+                match &e {
+                    ProxyError::RateLimited(upstream) => {
+                        let cooldown = upstream
+                            .retry_after
+                            .unwrap_or(Duration::from_secs(config.rotation.cooldown_secs));
+                        state.account_manager.cool_down(&account, cooldown);
+                    }
+                    ProxyError::Auth(_) => {
+                        let cooldown = Duration::from_secs(config.rotation.cooldown_secs);
+                        state.account_manager.cool_down(&account, cooldown);
+                    }
+                    _ => {}
+                }
+
+                if is_last || !e.is_retryable() {
+                    tracing::error!("{} account '{}' failed ({}): {}", provider, account.name, e.label(), e);
+                    return Err(e);
+                }
+
+                tracing::warn!("{} account '{}' failed ({}), falling back: {}", provider, account.name, e.label(), e);
+                state.metrics.record_failover(&provider, &metric_model, e.label());
+                unavailable.push(e.to_string());
+                continue;
+            }
+        };
+
+        // After a fallback, the body names the model that served it instead
+        // of the one requested; the header shows it without parsing the body
+        if i > 0 {
+            let streaming = providers::is_event_stream(response.headers());
+            response.headers_mut().remove(header::CONTENT_LENGTH);
+            let (parts, body) = response.into_parts();
+            response = Response::from_parts(parts, providers::rewrite_model(body, streaming, &target.model));
+        }
+        if let Ok(value) = HeaderValue::from_str(&target.model) {
+            response.headers_mut().insert(SERVED_MODEL_HEADER, value);
+        }
+
+        return Ok(response);
+    }
+
+    Err(ProxyError::Unavailable(unavailable.join("; ")))
 }
 
-fn determine_provider(model: &str) -> Option<Provider> {
-    if Provider::Codex.matches_model(model) {
-        Some(Provider::Codex)
-    } else if Provider::Claude.matches_model(model) {
-        Some(Provider::Claude)
-    } else if Provider::Gemini.matches_model(model) {
-        Some(Provider::Gemini)
-    } else {
-        None
+/// Provider name from the OpenTelemetry GenAI semantic conventions
+fn genai_provider(provider: &Provider) -> &'static str {
+    match provider {
+        Provider::Codex => "openai",
+        Provider::Claude => "anthropic",
+        Provider::Gemini => "gcp.gemini",
+    }
+}
+
+/// An error response in the OpenAI error format
+pub(super) fn error(status: StatusCode, message: impl Into<String>) -> (StatusCode, Json<Value>) {
+    (status, Json(providers::openai_error(status, message, None)))
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{SpanKind, TracerProvider as _};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+    use tower::ServiceExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::accounts::{Credentials, Egress};
+    use crate::config::CredentialsConfig;
+
+    /// Anthropic stand-in: `claude-down` is unavailable, anything else answers
+    async fn messages(Json(body): Json<Value>) -> Response<Body> {
+        let model = body["model"].as_str().unwrap_or_default();
+        if model == "claude-down" {
+            let error = json!({"type": "error", "error": {"type": "overloaded_error", "message": "down"}});
+            return (StatusCode::SERVICE_UNAVAILABLE, Json(error)).into_response();
+        }
+        Json(json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": model,
+            "content": [{"type": "text", "text": "hi"}],
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 12, "output_tokens": 5},
+        }))
+        .into_response()
+    }
+
+    async fn router() -> Router {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, Router::new().route("/messages", post(messages))).await });
+
+        let config: Config = toml::from_str(&format!(
+            "[fallbacks]\nclaude-down = [\"claude-up\"]\n\n[http.providers.claude]\nbase_url = \"{}\"\n",
+            base
+        ))
+        .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("omniproxy-router-{:x}", rand::random::<u64>()));
+        let mut accounts = AccountManager::load_from(dir.join("accounts.json"), &CredentialsConfig::default()).await.unwrap();
+        let credentials = Credentials::new("token".to_string(), "refresh".to_string(), chrono::Utc::now() + chrono::Duration::hours(1));
+        accounts.add(Provider::Claude, "a1", credentials, Egress::default()).await.unwrap();
+
+        create_router(
+            Arc::new(accounts),
+            LiveConfig::new(config).unwrap(),
+            Arc::new(RwLock::new(ModelRegistry::default())),
+            Arc::new(Metrics::new().unwrap()),
+        )
+    }
+
+    /// The value last recorded for `key`; re-recorded fields repeat in the export
+    fn attribute(span: &SpanData, key: &str) -> Option<opentelemetry::Value> {
+        span.attributes.iter().rev().find(|kv| kv.key.as_str() == key).map(|kv| kv.value.clone())
+    }
+
+    #[tokio::test]
+    async fn fallback_attempts_are_sibling_upstream_spans_under_chat() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let request = Request::post("/v1/chat/completions")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({"model": "claude-down", "messages": [{"role": "user", "content": "hello"}]}).to_string()))
+            .unwrap();
+        let response = router().await.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.into_body().collect().await.unwrap();
+
+        provider.force_flush().unwrap();
+        let spans = exporter.get_finished_spans().unwrap();
+
+        let chat = spans.iter().find(|s| s.name == "chat").expect("chat span");
+        let mut upstream: Vec<_> = spans.iter().filter(|s| s.span_kind == SpanKind::Client).collect();
+        upstream.sort_by_key(|s| s.start_time);
+        let names: Vec<_> = upstream.iter().map(|s| s.name.as_ref()).collect();
+        assert_eq!(names, ["chat claude-down", "chat claude-up"]);
+        assert!(upstream.iter().all(|s| s.parent_span_id == chat.span_context.span_id()));
+
+        assert_eq!(attribute(upstream[0], "http.response.status_code"), Some(503i64.into()));
+        assert_eq!(attribute(upstream[0], "error.type"), Some("upstream_server_error".into()));
+        assert_eq!(attribute(upstream[1], "http.response.status_code"), Some(200i64.into()));
+        assert_eq!(attribute(upstream[1], "gen_ai.provider.name"), Some("anthropic".into()));
+
+        assert_eq!(attribute(chat, "gen_ai.operation.name"), Some("chat".into()));
+        assert_eq!(attribute(chat, "gen_ai.request.model"), Some("claude-down".into()));
+        assert_eq!(attribute(chat, "gen_ai.response.model"), Some("claude-up".into()));
+        assert_eq!(attribute(chat, "gen_ai.provider.name"), Some("anthropic".into()));
+        assert_eq!(attribute(chat, "gen_ai.usage.input_tokens"), Some(12i64.into()));
+        assert_eq!(attribute(chat, "gen_ai.usage.output_tokens"), Some(5i64.into()));
+        assert_eq!(attribute(chat, "omniproxy.account"), Some("a1".into()));
     }
 }
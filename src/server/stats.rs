@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::metrics::{self, RequestLabels};
+
+/// Minutes of request rate history kept
+const RATE_WINDOW_MINUTES: i64 = 60;
+
+/// Errors kept for the dashboard
+const RECENT_ERRORS: usize = 50;
+
+/// Request history for the dashboard, since the server started
+#[derive(Default)]
+pub struct Stats {
+    models: Mutex<BTreeMap<String, ModelUsage>>,
+    rates: Mutex<VecDeque<RateBucket>>,
+    errors: Mutex<VecDeque<RecentError>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelUsage {
+    pub requests: u64,
+    pub errors: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Requests that finished in one minute
+#[derive(Debug, Clone, Serialize)]
+pub struct RateBucket {
+    pub minute: DateTime<Utc>,
+    pub requests: u64,
+    pub errors: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentError {
+    pub time: DateTime<Utc>,
+    pub request_id: String,
+    pub status: u16,
+    pub error: String,
+    pub message: String,
+    pub provider: String,
+    pub model: String,
+    pub account: String,
+}
+
+impl Stats {
+    /// Count a finished request
+    pub fn record_request(&self, labels: &RequestLabels, is_error: bool) {
+        {
+            let mut models = self.models.lock().unwrap();
+            let usage = models.entry(model_key(labels).to_string()).or_default();
+            usage.requests += 1;
+            usage.errors += is_error as u64;
+        }
+
+        let minute = current_minute();
+        let mut rates = self.rates.lock().unwrap();
+        match rates.back_mut() {
+            Some(bucket) if bucket.minute == minute => {
+                bucket.requests += 1;
+                bucket.errors += is_error as u64;
+            }
+            _ => rates.push_back(RateBucket {
+                minute,
+                requests: 1,
+                errors: is_error as u64,
+            }),
+        }
+
+        let oldest = minute - chrono::Duration::minutes(RATE_WINDOW_MINUTES);
+        while rates.front().is_some_and(|bucket| bucket.minute <= oldest) {
+            rates.pop_front();
+        }
+    }
+
+    /// Add the tokens a model's response reported
+    pub fn record_tokens(&self, labels: &RequestLabels, prompt_tokens: u64, completion_tokens: u64) {
+        let mut models = self.models.lock().unwrap();
+        let usage = models.entry(model_key(labels).to_string()).or_default();
+        usage.prompt_tokens += prompt_tokens;
+        usage.completion_tokens += completion_tokens;
+    }
+
+    pub fn record_error(&self, error: RecentError) {
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() == RECENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(error);
+    }
+
+    pub fn models(&self) -> BTreeMap<String, ModelUsage> {
+        self.models.lock().unwrap().clone()
+    }
+
+    /// Requests per minute over the last hour, oldest first, with idle minutes as zeros
+    pub fn rates(&self) -> Vec<RateBucket> {
+        let rates = self.rates.lock().unwrap();
+        let now = current_minute();
+
+        (0..RATE_WINDOW_MINUTES)
+            .rev()
+            .map(|ago| {
+                let minute = now - chrono::Duration::minutes(ago);
+                rates
+                    .iter()
+                    .find(|bucket| bucket.minute == minute)
+                    .cloned()
+                    .unwrap_or(RateBucket {
+                        minute,
+                        requests: 0,
+                        errors: 0,
+                    })
+            })
+            .collect()
+    }
+
+    /// Recent errors, newest first
+    pub fn errors(&self) -> Vec<RecentError> {
+        self.errors.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+/// The table is keyed like the metrics: by models the registry knows, with
+/// unknown models and requests that never reached a provider sharing one
+/// entry, so made-up model names can't grow it
+fn model_key(labels: &RequestLabels) -> &str {
+    if labels.provider == "none" {
+        metrics::UNKNOWN_MODEL
+    } else {
+        &labels.model
+    }
+}
+
+fn current_minute() -> DateTime<Utc> {
+    let now = Utc::now().timestamp();
+    DateTime::from_timestamp(now - now.rem_euclid(60), 0).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(provider: &str, model: &str) -> RequestLabels {
+        RequestLabels {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            account: "none".to_string(),
+            client: "none".to_string(),
+        }
+    }
+
+    #[test]
+    fn unrouted_requests_share_one_model_entry() {
+        let stats = Stats::default();
+
+        stats.record_request(&labels("none", "typo-1"), true);
+        stats.record_request(&labels("none", "typo-2"), true);
+        stats.record_request(&labels("claude", "claude-sonnet-4"), false);
+
+        let models = stats.models();
+        assert_eq!(models.keys().collect::<Vec<_>>(), ["claude-sonnet-4", "unknown"]);
+        assert_eq!(models["unknown"].requests, 2);
+        assert_eq!(models["unknown"].errors, 2);
+    }
+
+    #[test]
+    fn tokens_land_in_the_request_entry() {
+        let stats = Stats::default();
+
+        stats.record_request(&labels("claude", metrics::UNKNOWN_MODEL), false);
+        stats.record_tokens(&labels("claude", metrics::UNKNOWN_MODEL), 10, 5);
+        stats.record_tokens(&labels("none", "typo"), 1, 1);
+
+        let models = stats.models();
+        assert_eq!(models.keys().collect::<Vec<_>>(), ["unknown"]);
+        assert_eq!(models["unknown"].requests, 1);
+        assert_eq!(models["unknown"].prompt_tokens, 11);
+        assert_eq!(models["unknown"].completion_tokens, 6);
+    }
+
+    #[test]
+    fn rates_cover_the_window_with_idle_minutes() {
+        let stats = Stats::default();
+
+        stats.record_request(&labels("codex", "gpt-5"), false);
+        stats.record_request(&labels("codex", "gpt-5"), true);
+
+        let rates = stats.rates();
+        assert_eq!(rates.len(), RATE_WINDOW_MINUTES as usize);
+        // Both may land in the last two buckets if a minute turns over in between
+        let requests: u64 = rates.iter().map(|b| b.requests).sum();
+        let errors: u64 = rates.iter().map(|b| b.errors).sum();
+        assert_eq!((requests, errors), (2, 1));
+        assert!(rates[..rates.len() - 2].iter().all(|b| b.requests == 0));
+    }
+}